@@ -0,0 +1,89 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use stock_trading_simulator::{find_market_price, MarketData, MarketSnapshot, Order, OrderType, Portfolio, TimeInForce};
+
+const SIZES: [usize; 3] = [10, 100, 1000];
+
+fn market_data(size: usize) -> Vec<MarketData> {
+    (0..size)
+        .map(|i| MarketData::new(&format!("SYM{}", i), Decimal::from(10 + i % 90)))
+        .collect()
+}
+
+// A portfolio holding one share of every symbol in the market data
+fn portfolio(market_data: &[MarketData]) -> Portfolio {
+    let mut portfolio = Portfolio::new(dec!(1000000000));
+    for quote in market_data {
+        let order = Order {
+            symbol: quote.symbol.clone(),
+            quantity: dec!(1),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        };
+        portfolio.execute_order(&order, quote);
+    }
+    portfolio
+}
+
+fn execute_order(c: &mut Criterion) {
+    let mut group = c.benchmark_group("execute_order");
+    for size in SIZES {
+        let market_data = market_data(size);
+        let snapshot = MarketSnapshot::new(&market_data);
+        let order = Order {
+            symbol: format!("SYM{}", size - 1),
+            quantity: dec!(1),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        };
+
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter_batched(
+                || portfolio(&market_data),
+                |mut portfolio| portfolio.execute_order(black_box(&order), snapshot.quote(&order.symbol).unwrap()),
+                criterion::BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn calculate_profit_loss(c: &mut Criterion) {
+    let mut group = c.benchmark_group("calculate_profit_loss");
+    for size in SIZES {
+        let market_data = market_data(size);
+        let portfolio = portfolio(&market_data);
+
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter(|| portfolio.calculate_profit_loss(black_box(&market_data)))
+        });
+    }
+    group.finish();
+}
+
+// Looking up every symbol once: a linear scan per lookup against a snapshot
+// built once and indexed by symbol
+fn price_lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("price_lookup");
+    for size in SIZES {
+        let market_data = market_data(size);
+        let symbols: Vec<String> = market_data.iter().map(|quote| quote.symbol.clone()).collect();
+
+        group.bench_with_input(BenchmarkId::new("find_market_price", size), &size, |b, _| {
+            b.iter(|| symbols.iter().filter_map(|symbol| find_market_price(&market_data, symbol)).sum::<Decimal>())
+        });
+        group.bench_with_input(BenchmarkId::new("snapshot", size), &size, |b, _| {
+            b.iter(|| {
+                let snapshot = MarketSnapshot::new(&market_data);
+                symbols.iter().filter_map(|symbol| snapshot.price(symbol)).sum::<Decimal>()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, execute_order, calculate_profit_loss, price_lookup);
+criterion_main!(benches);
@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use stock_trading_simulator::{read_market_data, CsvOptions, InvalidPricePolicy, MarketData};
+
+// Any input may be rejected, but parsing must not panic and every quote that
+// comes back must have a strictly positive price, bid and ask
+fuzz_target!(|data: &[u8]| {
+    for invalid_prices in [InvalidPricePolicy::Error, InvalidPricePolicy::Skip] {
+        let options = CsvOptions { invalid_prices, ..CsvOptions::default() };
+        if let Ok(quotes) = read_market_data(data, &options) {
+            assert!(quotes.iter().all(MarketData::has_valid_prices));
+        }
+    }
+});
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+
+use crate::market_data::{normalize_symbol, MarketSnapshot};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Above, // Fires when the price rises to or through the threshold
+    Below, // Fires when the price falls to or through the threshold
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub symbol: String,
+    pub threshold: Decimal,
+    pub direction: Direction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriggeredAlert {
+    pub alert: Alert,
+    pub previous_price: Decimal,
+    pub price: Decimal,
+}
+
+// Registered alerts plus the last price seen for each symbol. Alerts are edge
+// triggered: one fires on the snapshot where the price crosses its threshold,
+// and not again until the price has gone back and crossed once more.
+#[derive(Debug, Clone, Default)]
+pub struct Alerts {
+    alerts: Vec<Alert>,
+    last_prices: HashMap<String, Decimal>,
+}
+
+impl Alerts {
+    pub fn new() -> Self {
+        Alerts::default()
+    }
+
+    pub fn add(&mut self, symbol: &str, threshold: Decimal, direction: Direction) {
+        self.alerts.push(Alert {
+            symbol: normalize_symbol(symbol),
+            threshold,
+            direction,
+        });
+    }
+
+    pub fn alerts(&self) -> &[Alert] {
+        &self.alerts
+    }
+
+    // Returns the alerts whose threshold was crossed between the previous
+    // snapshot and this one, in the order they were added. Nothing can fire on
+    // the first price seen for a symbol, as there is no crossing yet.
+    pub fn check_alerts(&mut self, snapshot: &MarketSnapshot) -> Vec<TriggeredAlert> {
+        let mut triggered = Vec::new();
+
+        for alert in &self.alerts {
+            let (Some(&previous_price), Some(price)) = (self.last_prices.get(&alert.symbol), snapshot.price(&alert.symbol)) else {
+                continue;
+            };
+
+            let crossed = match alert.direction {
+                Direction::Above => previous_price < alert.threshold && price >= alert.threshold,
+                Direction::Below => previous_price > alert.threshold && price <= alert.threshold,
+            };
+            if crossed {
+                triggered.push(TriggeredAlert {
+                    alert: alert.clone(),
+                    previous_price,
+                    price,
+                });
+            }
+        }
+
+        for alert in &self.alerts {
+            if let Some(price) = snapshot.price(&alert.symbol) {
+                self.last_prices.insert(alert.symbol.clone(), price);
+            }
+        }
+
+        triggered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::market_data::MarketData;
+
+    fn snapshot(price: Decimal) -> MarketSnapshot {
+        MarketSnapshot::new(&[MarketData::new("AAPL", price)])
+    }
+
+    #[test]
+    fn upward_crossing_fires_once() {
+        let mut alerts = Alerts::new();
+        alerts.add("aapl", dec!(200), Direction::Above);
+
+        assert!(alerts.check_alerts(&snapshot(dec!(195))).is_empty());
+        let fired = alerts.check_alerts(&snapshot(dec!(201)));
+        assert_eq!(fired.len(), 1);
+        assert_eq!((fired[0].previous_price, fired[0].price), (dec!(195), dec!(201)));
+
+        // Staying above doesn't fire again, but a fresh crossing does
+        assert!(alerts.check_alerts(&snapshot(dec!(205))).is_empty());
+        assert!(alerts.check_alerts(&snapshot(dec!(199))).is_empty());
+        assert_eq!(alerts.check_alerts(&snapshot(dec!(200))).len(), 1);
+    }
+
+    #[test]
+    fn downward_crossing_fires_once() {
+        let mut alerts = Alerts::new();
+        alerts.add("AAPL", dec!(150), Direction::Below);
+        alerts.add("AAPL", dec!(250), Direction::Above);
+
+        assert!(alerts.check_alerts(&snapshot(dec!(160))).is_empty());
+        let fired = alerts.check_alerts(&snapshot(dec!(149)));
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].alert.direction, Direction::Below);
+        assert!(alerts.check_alerts(&snapshot(dec!(140))).is_empty());
+    }
+}
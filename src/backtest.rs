@@ -0,0 +1,392 @@
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use rust_decimal::Decimal;
+
+use crate::bars::Ohlc;
+use crate::market_data::{MarketData, MarketSnapshot};
+use crate::order::{Order, OrderType};
+use crate::portfolio::Portfolio;
+use crate::strategy::{BuyAndHold, Strategy};
+
+#[derive(Debug, Clone, Default)]
+pub struct BacktestOptions {
+    // Halt a symbol for a tick when its price moves by more than this fraction
+    // of the previous snapshot's price, e.g. 0.1 for 10%
+    pub circuit_breaker_pct: Option<Decimal>,
+    // Ticks between a market order being submitted and it filling, at the
+    // price of the snapshot it becomes eligible on. Zero fills immediately.
+    pub latency_ticks: usize,
+    // Show a progress bar with an ETA on stderr, advanced once per snapshot.
+    // It stays hidden when stderr isn't a terminal.
+    pub progress: bool,
+}
+
+// Replays the snapshots in order against the portfolio. On each tick resting
+// orders are re-evaluated, the strategy's orders are executed (those for
+// symbols missing from the snapshot are dropped), and the resulting market
+// value is added to the equity curve under the snapshot's index. Each snapshot
+// stands for a trading day, so day orders still resting after it expire.
+// Returns the portfolio in its final state.
+pub fn backtest(portfolio: Portfolio, snapshots: Vec<Vec<MarketData>>, strategy: &mut dyn Strategy) -> Portfolio {
+    backtest_with_options(portfolio, snapshots, strategy, &BacktestOptions::default())
+}
+
+// Like backtest, with a circuit breaker: a symbol whose price jumps too far
+// between consecutive snapshots is halted for that tick, so its orders are
+// rejected with OrderError::TradingHalted and its resting orders wait. With
+// latency, market orders are queued and executed once their tick comes round,
+// before the strategy runs; any still queued after the last snapshot are
+// dropped.
+pub fn backtest_with_options(mut portfolio: Portfolio, snapshots: Vec<Vec<MarketData>>, strategy: &mut dyn Strategy, options: &BacktestOptions) -> Portfolio {
+    portfolio.record_equity = true;
+    let mut previous: Option<MarketSnapshot> = None;
+    let mut delayed: VecDeque<(usize, Order)> = VecDeque::new(); // Tick each order becomes eligible on
+    let progress = options.progress.then(|| progress_bar(snapshots.len()));
+
+    for (index, snapshot) in snapshots.iter().enumerate() {
+        let mut tick = || {
+            let quotes = MarketSnapshot::new(snapshot);
+            if let (Some(limit), Some(previous)) = (options.circuit_breaker_pct, &previous) {
+                portfolio.set_halted(halted_symbols(previous, snapshot, limit));
+            }
+            portfolio.process_pending(&quotes);
+
+            while delayed.front().is_some_and(|(eligible, _)| *eligible <= index) {
+                let (_, order) = delayed.pop_front().unwrap();
+                if let Some(quote) = quotes.quote(&order.symbol) {
+                    portfolio.execute_order(&order, quote);
+                }
+            }
+
+            for order in strategy.on_tick(&mut portfolio, snapshot) {
+                if options.latency_ticks > 0 && order.order_type == OrderType::Market {
+                    delayed.push_back((index + options.latency_ticks, order));
+                } else if let Some(quote) = quotes.quote(&order.symbol) {
+                    portfolio.execute_order(&order, quote);
+                }
+            }
+
+            portfolio.mark_equity(index as i64, snapshot);
+            portfolio.end_of_day();
+            previous = Some(quotes);
+        };
+
+        match &progress {
+            // Order logging is per tick, so the bar steps aside for it rather
+            // than being broken up mid-line
+            Some(bar) if log::log_enabled!(log::Level::Info) => bar.suspend(tick),
+            _ => tick(),
+        }
+        if let Some(bar) = &progress {
+            bar.inc(1);
+        }
+    }
+
+    if let Some(bar) = progress {
+        bar.finish_and_clear();
+    }
+    portfolio.set_halted(HashSet::new());
+    portfolio
+}
+
+// Like backtest, driven by bars. Resting orders are checked against each
+// bar's whole range through Portfolio::process_pending_bars, while the strategy
+// sees, new orders fill at, and the equity curve is marked at the closes. Day
+// orders expire when the bars move on to a later date.
+pub fn backtest_bars(mut portfolio: Portfolio, bars: Vec<Vec<Ohlc>>, strategy: &mut dyn Strategy) -> Portfolio {
+    portfolio.record_equity = true;
+    let mut day = None;
+
+    for (index, tick) in bars.iter().enumerate() {
+        let date = tick.first().map(|bar| bar.time.date());
+        if day.is_some_and(|day| date.is_some_and(|date| date > day)) {
+            portfolio.end_of_day();
+        }
+        day = date.or(day);
+
+        portfolio.process_pending_bars(tick);
+
+        let closes: Vec<MarketData> = tick.iter().map(Ohlc::close_quote).collect();
+        let quotes = MarketSnapshot::new(&closes);
+        for order in strategy.on_tick(&mut portfolio, &closes) {
+            if let Some(quote) = quotes.quote(&order.symbol) {
+                portfolio.execute_order(&order, quote);
+            }
+        }
+
+        portfolio.mark_equity(index as i64, &closes);
+    }
+
+    portfolio
+}
+
+fn progress_bar(len: usize) -> ProgressBar {
+    let bar = ProgressBar::new(len as u64);
+    if let Ok(style) = ProgressStyle::with_template("{bar:40} {pos}/{len} snapshots, ETA {eta}") {
+        bar.set_style(style);
+    }
+    bar
+}
+
+fn halted_symbols(previous: &MarketSnapshot, snapshot: &[MarketData], limit: Decimal) -> HashSet<String> {
+    snapshot
+        .iter()
+        .filter(|data| {
+            previous
+                .price(&data.symbol)
+                .is_some_and(|before| before > Decimal::ZERO && ((data.price - before) / before).abs() > limit)
+        })
+        .map(|data| data.symbol.clone())
+        .collect()
+}
+
+// One independent run for backtest_batch. Everything a run depends on is
+// owned by its config, and a backtest draws no random numbers of its own, so a
+// run is deterministic as long as its inputs are: random prices should come
+// from snapshots generated up front with a seed, e.g. by generate_random_walk,
+// and a strategy that randomizes should be seeded when it is built.
+pub struct BacktestConfig {
+    pub portfolio: Portfolio,
+    pub snapshots: Vec<Vec<MarketData>>,
+    pub strategy: Box<dyn Strategy + Send>,
+    pub options: BacktestOptions,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BacktestResult {
+    pub portfolio: Portfolio, // Final state, equity curve included
+    pub final_value: Decimal, // Market value at the last snapshot
+    pub percent_return: Option<f32>,
+    pub max_drawdown: f32,
+}
+
+impl BacktestConfig {
+    pub fn run(mut self) -> BacktestResult {
+        let last = self.snapshots.last().cloned().unwrap_or_default();
+        let portfolio = backtest_with_options(self.portfolio, self.snapshots, self.strategy.as_mut(), &self.options);
+
+        BacktestResult {
+            final_value: portfolio.market_value(&last),
+            percent_return: portfolio.percent_return(&last),
+            max_drawdown: portfolio.max_drawdown(),
+            portfolio,
+        }
+    }
+}
+
+// Runs each config on the rayon thread pool. Results come back in config
+// order and match running the configs one after another.
+pub fn backtest_batch(configs: Vec<BacktestConfig>) -> Vec<BacktestResult> {
+    configs.into_par_iter().map(BacktestConfig::run).collect()
+}
+
+// A strategy's run set against buy-and-hold over the same snapshots
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub strategy_curve: Vec<(i64, Decimal)>,
+    pub benchmark_curve: Vec<(i64, Decimal)>, // Buy-and-hold equity at the same indices
+    pub strategy_return: Option<f32>,
+    pub benchmark_return: Option<f32>,
+    pub excess_return: Option<f32>, // Strategy return less buy-and-hold's; positive when it added value
+}
+
+impl Comparison {
+    pub fn beat_buy_and_hold(&self) -> bool {
+        self.excess_return.is_some_and(|excess| excess > 0.0)
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = |value: Option<f32>| value.map_or("n/a".to_string(), |value| format!("{:+.2}%", value * 100.0));
+        writeln!(f, "Strategy return: {}", percent(self.strategy_return))?;
+        writeln!(f, "Buy and hold return: {}", percent(self.benchmark_return))?;
+        let verdict = if self.beat_buy_and_hold() { "added value" } else { "did not beat buy and hold" };
+        write!(f, "Excess return: {} ({})", percent(self.excess_return), verdict)
+    }
+}
+
+// Backtests buying the symbol with all of the strategy's starting cash and
+// holding it over the same snapshots, and compares the two runs. The
+// benchmark portfolio has no commission or slippage, so it is what the market
+// returned before costs.
+pub fn compare_to_buy_and_hold(strategy_result: &BacktestResult, symbol: &str, snapshots: &[Vec<MarketData>]) -> Comparison {
+    let initial_cash = strategy_result.portfolio.initial_cash();
+    let last = snapshots.last().cloned().unwrap_or_default();
+    let mut benchmark = BuyAndHold::new(symbol, initial_cash);
+    let portfolio = backtest(Portfolio::new(initial_cash), snapshots.to_vec(), &mut benchmark);
+
+    let benchmark_return = portfolio.percent_return(&last);
+    let excess_return = strategy_result.percent_return.zip(benchmark_return).map(|(strategy, benchmark)| strategy - benchmark);
+
+    Comparison {
+        strategy_curve: strategy_result.portfolio.equity_curve().to_vec(),
+        benchmark_curve: portfolio.equity_curve().to_vec(),
+        strategy_return: strategy_result.percent_return,
+        benchmark_return,
+        excess_return,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::order::TimeInForce;
+    use crate::strategy::MovingAverageCrossover;
+    use crate::synthetic::generate_random_walk;
+
+    // Buys one share of every quoted symbol on every tick
+    struct BuyEverything;
+
+    impl Strategy for BuyEverything {
+        fn on_tick(&mut self, _portfolio: &mut Portfolio, snapshot: &[MarketData]) -> Vec<Order> {
+            snapshot
+                .iter()
+                .map(|data| Order {
+                    symbol: data.symbol.clone(),
+                    quantity: dec!(1),
+                    order_type: OrderType::Market,
+                    time_in_force: TimeInForce::Gtc,
+                })
+                .collect()
+        }
+    }
+
+    // Places one limit buy at the given price on the first tick
+    struct LimitOnce {
+        limit: Decimal,
+        time_in_force: TimeInForce,
+        placed: bool,
+    }
+
+    impl Strategy for LimitOnce {
+        fn on_tick(&mut self, _portfolio: &mut Portfolio, _snapshot: &[MarketData]) -> Vec<Order> {
+            if std::mem::replace(&mut self.placed, true) {
+                return Vec::new();
+            }
+            vec![Order {
+                symbol: "AAPL".to_string(),
+                quantity: dec!(1),
+                order_type: OrderType::Limit(self.limit),
+                time_in_force: self.time_in_force,
+            }]
+        }
+    }
+
+    fn limit_once(time_in_force: TimeInForce) -> LimitOnce {
+        LimitOnce { limit: dec!(95), time_in_force, placed: false }
+    }
+
+    #[test]
+    fn day_orders_expire_after_the_snapshot_they_were_placed_on() {
+        let snapshots = || vec![vec![MarketData::new("AAPL", dec!(100))], vec![MarketData::new("AAPL", dec!(90))]];
+
+        let portfolio = backtest(Portfolio::default(), snapshots(), &mut limit_once(TimeInForce::Day));
+        assert!(portfolio.transactions().is_empty());
+        assert!(portfolio.open_orders().is_empty());
+        assert_eq!(portfolio.available_cash(), portfolio.cash);
+
+        let portfolio = backtest(Portfolio::default(), snapshots(), &mut limit_once(TimeInForce::Gtc));
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(1));
+    }
+
+    #[test]
+    fn day_orders_on_bars_last_until_the_date_changes() {
+        let bar = |time: &str, low| Ohlc {
+            time: chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap(),
+            symbol: "AAPL".to_string(),
+            open: dec!(100),
+            high: dec!(100),
+            low,
+            close: dec!(100),
+            volume: dec!(1000),
+        };
+        let run = |bars: Vec<Vec<Ohlc>>| backtest_bars(Portfolio::default(), bars, &mut limit_once(TimeInForce::Day));
+
+        // The dip later the same day fills the order, the one the next day doesn't
+        let same_day = run(vec![vec![bar("2024-03-01 10:00", dec!(99))], vec![bar("2024-03-01 15:00", dec!(94))]]);
+        assert_eq!(same_day.holdings["AAPL"].quantity, dec!(1));
+        let next_day = run(vec![vec![bar("2024-03-01 10:00", dec!(99))], vec![bar("2024-03-04 10:00", dec!(94))]]);
+        assert!(next_day.transactions().is_empty());
+        assert!(next_day.open_orders().is_empty());
+    }
+
+    #[test]
+    fn circuit_breaker_halts_only_the_symbol_that_jumped() {
+        let snapshots = vec![
+            vec![MarketData::new("AAPL", dec!(100)), MarketData::new("MSFT", dec!(200))],
+            vec![MarketData::new("AAPL", dec!(120)), MarketData::new("MSFT", dec!(202))],
+            vec![MarketData::new("AAPL", dec!(121)), MarketData::new("MSFT", dec!(204))],
+        ];
+        let options = BacktestOptions {
+            circuit_breaker_pct: Some(dec!(0.1)),
+            ..BacktestOptions::default()
+        };
+
+        let portfolio = backtest_with_options(Portfolio::default(), snapshots, &mut BuyEverything, &options);
+
+        // AAPL's 20% jump skips its second buy; the halt lasts a single tick
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(2));
+        assert_eq!(portfolio.holdings["MSFT"].quantity, dec!(3));
+    }
+
+    #[test]
+    fn latency_fills_market_orders_at_a_later_tick() {
+        let snapshots = vec![vec![MarketData::new("AAPL", dec!(100))], vec![MarketData::new("AAPL", dec!(110))]];
+        let options = BacktestOptions {
+            latency_ticks: 1,
+            ..BacktestOptions::default()
+        };
+
+        let portfolio = backtest_with_options(Portfolio::default(), snapshots, &mut BuyEverything, &options);
+
+        // The first buy fills at the second tick's price; the second never comes due
+        assert_eq!(portfolio.transactions().len(), 1);
+        assert_eq!(portfolio.transactions()[0].price, dec!(110));
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(1));
+    }
+
+    #[test]
+    fn holding_cash_trails_a_rising_market() {
+        let snapshots = vec![vec![MarketData::new("AAPL", dec!(100))], vec![MarketData::new("AAPL", dec!(110))], vec![MarketData::new("AAPL", dec!(120))]];
+        let config = BacktestConfig {
+            portfolio: Portfolio::new(dec!(1000)),
+            snapshots: snapshots.clone(),
+            strategy: Box::new(BuyAndHold::new("AAPL", Decimal::ZERO)),
+            options: BacktestOptions::default(),
+        };
+
+        let comparison = compare_to_buy_and_hold(&config.run(), "AAPL", &snapshots);
+
+        assert_eq!(comparison.strategy_return, Some(0.0));
+        assert_eq!(comparison.benchmark_return, Some(0.2));
+        assert_eq!(comparison.benchmark_curve.last(), Some(&(2, dec!(1200))));
+        assert!(!comparison.beat_buy_and_hold());
+    }
+
+    #[test]
+    fn a_parallel_batch_matches_running_the_configs_in_turn() {
+        let configs = || {
+            (0..8u64)
+                .map(|seed| BacktestConfig {
+                    portfolio: Portfolio::default(),
+                    snapshots: generate_random_walk("AAPL", 100.0, 40, 0.03, seed).into_iter().map(|quote| vec![quote]).collect(),
+                    strategy: Box::new(MovingAverageCrossover::new(3, 8, dec!(10))),
+                    options: BacktestOptions::default(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let sequential: Vec<BacktestResult> = configs().into_iter().map(BacktestConfig::run).collect();
+        let parallel = backtest_batch(configs());
+
+        assert!(sequential.iter().any(|result| !result.portfolio.transactions().is_empty()));
+        assert_eq!(parallel, sequential);
+        assert_eq!(backtest_batch(configs()), parallel);
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use rust_decimal::Decimal;
+
+use crate::market_data::{normalize_symbol, parse_price, LoadError, MarketData};
+use crate::order::{Order, OrderId, OrderStatus, OrderType};
+use crate::portfolio::Portfolio;
+
+// One symbol's trading over a period, so triggers can be checked against the
+// whole range rather than only where the period closed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ohlc {
+    pub time: NaiveDateTime, // Start of the period
+    pub symbol: String,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: Decimal,
+}
+
+impl Ohlc {
+    // The bar as a single-price quote at its close
+    pub fn close_quote(&self) -> MarketData {
+        self.quote_at(self.close)
+    }
+
+    // The price the order would first have traded at within the bar. An order
+    // whose stop, limit or target lies inside the low-high range sees that
+    // price, or the open when the bar gapped through it; anything else,
+    // including market and trailing stop orders, sees the close.
+    pub fn quote_for(&self, order: &Order) -> MarketData {
+        let buy = order.quantity > Decimal::ZERO;
+        // Whether the bar reached a price from above or below, and where it
+        // first traded there: the open if it gapped past
+        let falling_to = |price: Decimal| (self.low <= price).then(|| self.open.min(price));
+        let rising_to = |price: Decimal| (self.high >= price).then(|| self.open.max(price));
+
+        let price = match order.order_type {
+            OrderType::Limit(price) | OrderType::TakeProfit(price) if buy => falling_to(price),
+            OrderType::Limit(price) | OrderType::TakeProfit(price) => rising_to(price),
+            OrderType::Stop(stop) | OrderType::StopLimit { stop, .. } if buy => rising_to(stop),
+            OrderType::Stop(stop) | OrderType::StopLimit { stop, .. } => falling_to(stop),
+            OrderType::Market | OrderType::TrailingStop { .. } => None,
+        };
+
+        self.quote_at(price.unwrap_or(self.close))
+    }
+
+    fn quote_at(&self, price: Decimal) -> MarketData {
+        MarketData::new(&self.symbol, price)
+    }
+}
+
+impl Portfolio {
+    // process_pending for a set of bars: each resting order is evaluated at the
+    // price Ohlc::quote_for gives it, so a stop inside a bar's range fills even
+    // when the bar closes beyond it
+    pub fn process_pending_bars(&mut self, bars: &[Ohlc]) -> Vec<(OrderId, OrderStatus)> {
+        let by_symbol: HashMap<String, &Ohlc> = bars.iter().map(|bar| (normalize_symbol(&bar.symbol), bar)).collect();
+
+        self.process_pending_with(|order| by_symbol.get(&normalize_symbol(&order.symbol)).map(|bar| bar.quote_for(order)))
+    }
+}
+
+// Loads time,symbol,open,high,low,close,volume rows into one group of bars per
+// time, in file order; rows for the same time are expected to be together.
+// Times are ISO dates (2024-01-02) or date times (2024-01-02 09:30:00). A
+// first row that doesn't parse is taken as a header. Prices must be positive
+// with the open and close inside the low-high range.
+pub fn load_bars(file_path: &str) -> Result<Vec<Vec<Ohlc>>, LoadError> {
+    read_bars(BufReader::new(File::open(file_path)?))
+}
+
+// load_bars from any buffered reader
+pub fn read_bars<R: BufRead>(reader: R) -> Result<Vec<Vec<Ohlc>>, LoadError> {
+    let mut groups: Vec<Vec<Ohlc>> = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let content = line.trim_start_matches('\u{feff}').trim();
+        if content.is_empty() {
+            continue;
+        }
+
+        let Some(bar) = parse_bar(content) else {
+            if index == 0 {
+                continue;
+            }
+            return Err(LoadError::Parse { line: index + 1, content: line });
+        };
+        if bar.low <= Decimal::ZERO {
+            return Err(LoadError::InvalidPrice { line: index + 1, content: line });
+        }
+
+        match groups.last_mut() {
+            Some(bars) if bars[0].time == bar.time => bars.push(bar),
+            _ => groups.push(vec![bar]),
+        }
+    }
+
+    Ok(groups)
+}
+
+fn parse_bar(line: &str) -> Option<Ohlc> {
+    let parts: Vec<&str> = line.split(',').map(str::trim).collect();
+    let [time, symbol, open, high, low, close, volume] = parts.as_slice() else {
+        return None;
+    };
+    if symbol.is_empty() {
+        return None;
+    }
+
+    let bar = Ohlc {
+        time: parse_time(time)?,
+        symbol: normalize_symbol(symbol),
+        open: parse_price(open)?,
+        high: parse_price(high)?,
+        low: parse_price(low)?,
+        close: parse_price(close)?,
+        volume: Decimal::from_str(volume).ok()?,
+    };
+    let in_range = |price: Decimal| bar.low <= price && price <= bar.high;
+    if !in_range(bar.open) || !in_range(bar.close) {
+        return None;
+    }
+
+    Some(bar)
+}
+
+fn parse_time(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))
+}
+
+// Calls on_bar for each bar in turn, waiting between bars for the gap between
+// their times divided by speed, so 1 replays in real time and 10 ten times
+// faster. A speed of zero, infinity or anything else not positive and finite
+// replays as fast as possible, as do bars out of time order.
+pub fn replay(bars: Vec<Ohlc>, speed: f32, on_bar: impl FnMut(&Ohlc)) {
+    replay_with(bars, speed, on_bar, thread::sleep);
+}
+
+// replay with the wait supplied by the caller
+pub fn replay_with(bars: Vec<Ohlc>, speed: f32, mut on_bar: impl FnMut(&Ohlc), mut sleep: impl FnMut(Duration)) {
+    let paced = speed.is_finite() && speed > 0.0;
+    let mut previous: Option<NaiveDateTime> = None;
+
+    for bar in &bars {
+        if let (true, Some(previous)) = (paced, previous) {
+            let gap = (bar.time - previous).to_std().unwrap_or_default();
+            if !gap.is_zero() {
+                sleep(Duration::try_from_secs_f64(gap.as_secs_f64() / speed as f64).unwrap_or(Duration::MAX));
+            }
+        }
+        previous = Some(bar.time);
+        on_bar(bar);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::order::TimeInForce;
+
+    fn bar(symbol: &str, open: Decimal, high: Decimal, low: Decimal, close: Decimal) -> Ohlc {
+        Ohlc {
+            time: NaiveDate::from_ymd_opt(2024, 1, 3).unwrap().and_hms_opt(0, 0, 0).unwrap(),
+            symbol: symbol.to_string(),
+            open,
+            high,
+            low,
+            close,
+            volume: dec!(1000),
+        }
+    }
+
+    #[test]
+    fn stop_inside_the_range_fills_at_the_stop() {
+        let mut portfolio = Portfolio::default();
+        let buy = Order {
+            symbol: "AAPL".to_string(),
+            quantity: dec!(10),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        };
+        portfolio.execute_order(&buy, &bar("AAPL", dec!(100), dec!(100), dec!(100), dec!(100)).close_quote());
+        let stop = Order {
+            quantity: dec!(-10),
+            order_type: OrderType::Stop(dec!(95)),
+            ..buy
+        };
+        let (id, _) = portfolio.execute_order(&stop, &bar("AAPL", dec!(100), dec!(100), dec!(100), dec!(100)).close_quote());
+
+        // Dips to 94 but closes at 101, above the stop
+        let results = portfolio.process_pending_bars(&[bar("AAPL", dec!(100), dec!(102), dec!(94), dec!(101))]);
+
+        assert_eq!(results, vec![(id, OrderStatus::Filled)]);
+        assert_eq!(portfolio.transactions().last().unwrap().price, dec!(95));
+        assert!(portfolio.holdings["AAPL"].quantity.is_zero());
+    }
+
+    #[test]
+    fn replay_waits_for_the_scaled_gap_between_bars() {
+        let at = |minute| {
+            let mut bar = bar("AAPL", dec!(100), dec!(100), dec!(100), dec!(100));
+            bar.time = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap().and_hms_opt(9, minute, 0).unwrap();
+            bar
+        };
+        let bars = vec![at(30), at(31), at(31), at(33)];
+
+        let mut waits = Vec::new();
+        let mut seen = 0;
+        replay_with(bars.clone(), 10.0, |_| seen += 1, |wait| waits.push(wait));
+        assert_eq!(seen, 4);
+        assert_eq!(waits, vec![Duration::from_secs(6), Duration::from_secs(12)]);
+
+        for speed in [0.0, f32::INFINITY] {
+            replay_with(bars.clone(), speed, |_| {}, |_| panic!("replay at {} should not wait", speed));
+        }
+    }
+
+    #[test]
+    fn load_bars_groups_rows_by_date() {
+        let csv = "date,symbol,open,high,low,close,volume\n\
+                   2024-01-02,AAPL,100,105,99,104,1000\n\
+                   2024-01-02,MSFT,300,301,295,296,1000\n\
+                   2024-01-03,aapl,104,106,103,105,1000\n";
+
+        let bars = read_bars(Cursor::new(csv)).unwrap();
+
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].len(), 2);
+        assert_eq!(bars[1], vec![bar("AAPL", dec!(104), dec!(106), dec!(103), dec!(105))]);
+    }
+
+    #[test]
+    fn load_bars_rejects_a_close_outside_the_range() {
+        let csv = "2024-01-02,AAPL,100,105,99,104,1000\n2024-01-03,AAPL,100,105,99,110,1000\n";
+        assert!(matches!(read_bars(Cursor::new(csv)), Err(LoadError::Parse { line: 2, .. })));
+    }
+}
@@ -0,0 +1,48 @@
+use std::env;
+use std::io::{self, IsTerminal};
+
+use owo_colors::OwoColorize;
+use rust_decimal::Decimal;
+use stock_trading_simulator::round_money;
+
+// Whether terminal output is colored: --color or --no-color decide when given,
+// otherwise only a terminal that hasn't asked for NO_COLOR gets color, so piped
+// output stays plain
+pub fn enabled(flag: Option<bool>) -> bool {
+    flag.unwrap_or_else(|| io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none())
+}
+
+// A dollar amount with the sign ahead of the dollar, e.g. -$12.50, green when
+// it's a gain and red when it's a loss
+pub fn money(value: Decimal, color: bool) -> String {
+    let value = round_money(value);
+    let sign = if value < Decimal::ZERO { "-" } else { "" };
+    paint(value, format!("{}${:.2}", sign, value.abs()), color)
+}
+
+// Already formatted text for value, green when it's a gain and red when it's
+// a loss, for the library's reports that take a paint function
+pub fn paint(value: Decimal, text: String, color: bool) -> String {
+    if !color || value.is_zero() {
+        text
+    } else if value > Decimal::ZERO {
+        text.green().to_string()
+    } else {
+        text.red().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn money_is_plain_without_color() {
+        assert_eq!(money(dec!(-12.5), false), "-$12.50");
+        assert_eq!(money(dec!(-0.001), false), "$0.00");
+        assert_eq!(money(dec!(12.5), true), "\u{1b}[32m$12.50\u{1b}[39m");
+        assert_eq!(money(dec!(-12.5), true), "\u{1b}[31m-$12.50\u{1b}[39m");
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use rust_decimal::prelude::{Signed, ToPrimitive};
+use rust_decimal::Decimal;
+
+use crate::money::round_money;
+use crate::portfolio::{Portfolio, Trade};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymbolCosts {
+    pub commission: Decimal,
+    pub slippage: Decimal, // Paid above the quote on buys and received below it on sells
+}
+
+impl SymbolCosts {
+    pub fn total(&self) -> Decimal {
+        self.commission + self.slippage
+    }
+}
+
+// What trading costs took out of the account over the transaction log
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CostReport {
+    pub per_symbol: BTreeMap<String, SymbolCosts>,
+    pub commission: Decimal,
+    pub slippage: Decimal,
+    pub gross_profit_loss: Decimal, // Realized profit or loss before the slippage on the closed shares; commission is excluded already
+}
+
+impl CostReport {
+    pub fn total(&self) -> Decimal {
+        self.commission + self.slippage
+    }
+
+    // Costs as a fraction of the gross profit or loss, e.g. 0.25 when they ate
+    // a quarter of it. None when there's no gross profit or loss to compare to.
+    pub fn cost_drag(&self) -> Option<f32> {
+        if self.gross_profit_loss.is_zero() {
+            return None;
+        }
+
+        (self.total() / self.gross_profit_loss.abs()).to_f32()
+    }
+}
+
+impl fmt::Display for CostReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (symbol, costs) in &self.per_symbol {
+            writeln!(f, "{}: commission ${:.2}, slippage ${:.2}", symbol, round_money(costs.commission), round_money(costs.slippage))?;
+        }
+        write!(
+            f,
+            "Total costs: ${:.2} (commission ${:.2}, slippage ${:.2})",
+            round_money(self.total()),
+            round_money(self.commission),
+            round_money(self.slippage)
+        )?;
+        match self.cost_drag() {
+            Some(drag) => write!(f, ", {:.2}% of gross profit or loss ${:.2}", drag * 100.0, round_money(self.gross_profit_loss)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Portfolio {
+    // Sums the commission and slippage of every trade, per symbol and overall.
+    // Slippage is the difference between each fill's price and the quote it
+    // was made against, so buys filled at a limit above the quote count too.
+    pub fn cost_report(&self) -> CostReport {
+        let mut report = CostReport::default();
+
+        for trade in self.transactions() {
+            let slippage = (trade.price - trade.quoted_price) * trade.quantity;
+            let costs = report.per_symbol.entry(trade.symbol.clone()).or_default();
+            costs.commission += trade.commission;
+            costs.slippage += slippage;
+            report.commission += trade.commission;
+            report.slippage += slippage;
+        }
+
+        report.gross_profit_loss = self.realized_pl() + self.closed_slippage();
+        report
+    }
+
+    // The slippage behind the realized profit or loss: for every closed piece,
+    // what the closing trade gave up on those shares plus what was paid opening
+    // them. Slippage on shares still held stays out, as it isn't realized yet.
+    fn closed_slippage(&self) -> Decimal {
+        let trades = self.transactions();
+        let trade = |sequence: u64| &trades[sequence as usize - 1];
+        let per_share = |trade: &Trade| (trade.price - trade.quoted_price) * trade.quantity.signum();
+
+        // Under average cost a closed piece carries the average opening
+        // slippage of the position it came out of, as of the closing trade
+        let mut open: HashMap<&str, (Decimal, Decimal)> = HashMap::new(); // Symbol to quantity held and its opening slippage
+        let mut average_before = Vec::with_capacity(trades.len());
+        for trade in trades {
+            let (held, slippage) = open.entry(trade.symbol.as_str()).or_default();
+            let average = if held.is_zero() { Decimal::ZERO } else { *slippage / held.abs() };
+            average_before.push(average);
+
+            if held.is_zero() || held.signum() == trade.quantity.signum() {
+                *slippage += per_share(trade) * trade.quantity.abs();
+            } else {
+                *slippage -= average * trade.quantity.abs().min(held.abs());
+            }
+            *held += trade.quantity;
+            // A trade past flat opens the other way with what's left of it
+            if !held.is_zero() && held.signum() == trade.quantity.signum() && trade.quantity.abs() > held.abs() {
+                *slippage = per_share(trade) * held.abs();
+            }
+        }
+
+        self.realized_gains()
+            .iter()
+            .map(|gain| {
+                let opening = match gain.opened_index {
+                    Some(sequence) => per_share(trade(sequence)),
+                    None => average_before[gain.closed_index as usize - 1],
+                };
+                (opening + per_share(trade(gain.closed_index))) * gain.quantity.abs()
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::market_data::MarketData;
+    use crate::order::{Order, OrderType, TimeInForce};
+    use crate::portfolio::{CommissionModel, LotMethod, Slippage};
+
+    fn trade(portfolio: &mut Portfolio, symbol: &str, quantity: Decimal, price: Decimal) {
+        let order = Order {
+            symbol: symbol.to_string(),
+            quantity,
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        };
+        portfolio.execute_order(&order, &MarketData::new(symbol, price));
+    }
+
+    #[test]
+    fn cost_report_sums_commission_and_slippage_per_symbol() {
+        let mut portfolio = Portfolio::new(dec!(10000));
+        portfolio.commission = CommissionModel::PerTrade(dec!(1));
+        portfolio.slippage = Slippage::Fixed(dec!(0.5));
+
+        // Bought at 100.5 and sold at 109.5: 90 realized, 10 of it lost to
+        // slippage. MSFT's is still open, so it's no part of the gross.
+        trade(&mut portfolio, "AAPL", dec!(10), dec!(100));
+        trade(&mut portfolio, "AAPL", dec!(-10), dec!(110));
+        trade(&mut portfolio, "MSFT", dec!(2), dec!(50));
+
+        let report = portfolio.cost_report();
+
+        assert_eq!(report.per_symbol["AAPL"], SymbolCosts { commission: dec!(2), slippage: dec!(10) });
+        assert_eq!(report.per_symbol["MSFT"], SymbolCosts { commission: dec!(1), slippage: dec!(1) });
+        assert_eq!(report.total(), dec!(14));
+        assert_eq!(report.gross_profit_loss, dec!(100));
+    }
+
+    #[test]
+    fn gross_profit_loss_only_adds_back_slippage_on_closed_shares() {
+        let gross = |lot_method| {
+            let mut portfolio = Portfolio::new(dec!(100000));
+            portfolio.slippage = Slippage::Percent(dec!(0.01));
+            portfolio.lot_method = lot_method;
+            trade(&mut portfolio, "AAPL", dec!(10), dec!(100));
+            trade(&mut portfolio, "AAPL", dec!(10), dec!(200));
+            trade(&mut portfolio, "AAPL", dec!(-15), dec!(300));
+            portfolio.cost_report().gross_profit_loss
+        };
+
+        // At quoted prices, selling 15 at 300 gains 200 a share on the lot
+        // bought at 100 and 100 on the one at 200, or 150 on the average
+        assert_eq!(gross(LotMethod::Fifo), dec!(10) * dec!(200) + dec!(5) * dec!(100));
+        assert_eq!(gross(LotMethod::Lifo), dec!(10) * dec!(100) + dec!(5) * dec!(200));
+        assert_eq!(gross(LotMethod::AverageCost), dec!(2250));
+    }
+
+    #[test]
+    fn cost_drag_is_none_without_profit_or_loss() {
+        assert_eq!(Portfolio::default().cost_report().cost_drag(), None);
+    }
+}
@@ -0,0 +1,257 @@
+use std::fmt;
+
+use rust_decimal::Decimal;
+
+use crate::market_data::{MarketData, MarketSnapshot};
+use crate::money::round_money;
+use crate::portfolio::{Portfolio, Position};
+
+impl fmt::Display for Portfolio {
+    // Cash, then each open position sorted by symbol, then equity with every
+    // position valued at its average cost
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Cash: ${:.2}", round_money(self.cash))?;
+        for (symbol, position) in self.sorted_positions() {
+            writeln!(f, "  {} {} @ ${:.2} avg cost", symbol, position.quantity, round_money(position.avg_cost))?;
+        }
+        write!(f, "Equity at cost: ${:.2}", round_money(self.book_value()))
+    }
+}
+
+// End-of-run figures for a portfolio, from Portfolio::summary_report
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub cash: Decimal,
+    pub equity: Decimal, // Cash plus holdings at market prices
+    pub profit_loss: Decimal, // Equity less the starting cash
+    pub percent_return: Option<f32>,
+    pub trades: usize,
+    pub win_rate: Option<f32>, // Fraction of closed positions that made money, None before any closed
+    pub commissions: Decimal,
+}
+
+impl Summary {
+    // The Display text with the profit or loss passed through paint along with
+    // its value, e.g. to color it for a terminal
+    pub fn to_string_with(&self, paint: impl Fn(Decimal, String) -> String) -> String {
+        let percent = |value: Option<f32>| value.map_or("n/a".to_string(), |value| format!("{:.2}%", value * 100.0));
+        [
+            format!("Final cash: ${:.2}", round_money(self.cash)),
+            format!("Total equity: ${:.2}", round_money(self.equity)),
+            format!("Total P/L: {}", paint(self.profit_loss, format!("{:.2}", round_money(self.profit_loss)))),
+            format!("Total return: {}", percent(self.percent_return)),
+            format!("Trades: {}", self.trades),
+            format!("Win rate: {}", percent(self.win_rate)),
+            format!("Commissions: ${:.2}", round_money(self.commissions)),
+        ]
+        .join("\n")
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_string_with(|_, text| text))
+    }
+}
+
+impl Portfolio {
+    // Summarizes the run so far. The win rate counts each realized gain, so
+    // a sell matched against several lots counts once per lot.
+    pub fn summary_report(&self, current_market_data: &[MarketData]) -> Summary {
+        let closed = self.realized_gains();
+        let wins = closed.iter().filter(|gain| gain.gain > Decimal::ZERO).count();
+        let win_rate = (!closed.is_empty()).then(|| wins as f32 / closed.len() as f32);
+
+        Summary {
+            cash: self.cash,
+            equity: self.market_value(current_market_data),
+            profit_loss: self.calculate_profit_loss(current_market_data),
+            percent_return: self.percent_return(current_market_data),
+            trades: self.transactions().len(),
+            win_rate,
+            commissions: self.transactions().iter().map(|trade| trade.commission).sum(),
+        }
+    }
+
+    // Like the Display output, but with each position's market value and the
+    // total equity at current prices. Positions without a quote show no value.
+    pub fn summary(&self, current_market_data: &[MarketData]) -> String {
+        let snapshot = MarketSnapshot::new(current_market_data);
+        let mut lines = vec![format!("Cash: ${:.2}", round_money(self.cash))];
+
+        for (symbol, position) in self.sorted_positions() {
+            let value = match snapshot.price(symbol) {
+                Some(price) => format!("value ${:.2}", round_money(price * position.quantity)),
+                None => "no market data".to_string(),
+            };
+            lines.push(format!("  {} {} @ ${:.2} avg cost, {}", symbol, position.quantity, round_money(position.avg_cost), value));
+        }
+
+        lines.push(format!("Total equity: ${:.2}", round_money(self.market_value(current_market_data))));
+        lines.join("\n")
+    }
+
+    // Open positions as a table sorted by symbol, with the symbol column
+    // left-aligned and the numbers right-aligned. Columns that need a quote show
+    // a dash for a position without one.
+    pub fn holdings_table(&self, current_market_data: &[MarketData]) -> String {
+        self.holdings_table_with(current_market_data, |_, cell| cell)
+    }
+
+    // holdings_table with each unrealized profit or loss cell passed through
+    // paint along with its value, after padding so added escape codes don't
+    // throw the columns out of line
+    pub fn holdings_table_with(&self, current_market_data: &[MarketData], paint: impl Fn(Decimal, String) -> String) -> String {
+        let snapshot = MarketSnapshot::new(current_market_data);
+        let header = ["Symbol", "Quantity", "Avg Cost", "Last Price", "Market Value", "Unrealized P/L"].map(String::from);
+
+        let mut rows = vec![header];
+        let mut profit_losses = vec![None];
+        for (symbol, position) in self.sorted_positions() {
+            let price = snapshot.price(symbol);
+            let money = |value: Option<Decimal>| value.map_or("-".to_string(), |value| format!("{:.2}", round_money(value)));
+            let profit_loss = price.map(|price| (price - position.avg_cost) * position.quantity);
+            profit_losses.push(profit_loss);
+            rows.push([
+                symbol.clone(),
+                position.quantity.to_string(),
+                format!("{:.2}", round_money(position.avg_cost)),
+                money(price),
+                money(price.map(|price| price * position.quantity)),
+                money(profit_loss),
+            ]);
+        }
+
+        let mut widths = [0; 6];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let lines: Vec<String> = rows
+            .iter()
+            .zip(profit_losses)
+            .map(|(row, profit_loss)| {
+                let mut cells: Vec<String> = row
+                    .iter()
+                    .zip(widths)
+                    .enumerate()
+                    .map(|(column, (cell, width))| if column == 0 { format!("{:<width$}", cell) } else { format!("{:>width$}", cell) })
+                    .collect();
+                if let Some(profit_loss) = profit_loss {
+                    cells[5] = paint(profit_loss, std::mem::take(&mut cells[5]));
+                }
+                cells.join("  ")
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    pub fn print_holdings_table(&self, current_market_data: &[MarketData]) {
+        println!("{}", self.holdings_table(current_market_data));
+    }
+
+    // Positions that aren't flat, in symbol order
+    fn sorted_positions(&self) -> impl Iterator<Item = (&String, &Position)> {
+        self.holdings.iter().filter(|(_, position)| !position.quantity.is_zero())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::order::{Order, OrderType, TimeInForce};
+    use crate::portfolio::LotMethod;
+
+    fn trade(portfolio: &mut Portfolio, quantity: Decimal, price: Decimal) {
+        let order = Order {
+            symbol: "AAPL".to_string(),
+            quantity,
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        };
+        portfolio.execute_order(&order, &MarketData::new("AAPL", price));
+    }
+
+    #[test]
+    fn win_rate_counts_profitable_closes() {
+        let mut portfolio = Portfolio::new(dec!(10000));
+        portfolio.lot_method = LotMethod::Fifo;
+        trade(&mut portfolio, dec!(10), dec!(100));
+        trade(&mut portfolio, dec!(10), dec!(120));
+
+        // Closes the lot at 100 for a gain, then the one at 120 for a loss,
+        // then the rest of it for another loss
+        trade(&mut portfolio, dec!(-15), dec!(110));
+        trade(&mut portfolio, dec!(-5), dec!(90));
+        assert_eq!(portfolio.summary_report(&[]).win_rate, Some(1.0 / 3.0));
+
+        trade(&mut portfolio, dec!(1), dec!(90));
+        trade(&mut portfolio, dec!(-1), dec!(95));
+        let summary = portfolio.summary_report(&[MarketData::new("AAPL", dec!(95))]);
+        assert_eq!(summary.win_rate, Some(0.5));
+        assert_eq!(summary.trades, 6);
+    }
+
+    #[test]
+    fn win_rate_is_none_before_anything_closes() {
+        let mut portfolio = Portfolio::new(dec!(10000));
+        trade(&mut portfolio, dec!(10), dec!(100));
+
+        assert_eq!(portfolio.summary_report(&[]).win_rate, None);
+    }
+
+    #[test]
+    fn only_profit_and_loss_is_painted() {
+        let mut portfolio = Portfolio::new(dec!(10000));
+        trade(&mut portfolio, dec!(10), dec!(100));
+        let market_data = [MarketData::new("AAPL", dec!(98.5))];
+        let mark = |value: Decimal, text: String| if value < Decimal::ZERO { format!("[{}]", text) } else { text };
+
+        // The P/L cell is padded first, so unpainted the table is unchanged
+        let table = portfolio.holdings_table_with(&market_data, mark);
+        assert!(table.lines().nth(1).unwrap().ends_with("  [        -15.00]"));
+        assert_eq!(table.replace(['[', ']'], ""), portfolio.holdings_table(&market_data));
+
+        let summary = portfolio.summary_report(&market_data);
+        assert_eq!(summary.profit_loss, dec!(-15));
+        assert!(summary.to_string_with(mark).contains("Total P/L: [-15.00]"));
+        assert!(portfolio.statement_with(&market_data, mark).contains("Unrealized P/L: [        -15.00]"));
+    }
+
+    #[test]
+    fn reports_are_identical_across_runs() {
+        let symbols = ["TSLA", "AAPL", "NVDA", "MSFT", "GOOG", "AMZN", "META", "IBM"];
+        let run = || {
+            let mut portfolio = Portfolio::new(dec!(100000));
+            let market_data: Vec<MarketData> = symbols.iter().zip(1..).map(|(symbol, n)| MarketData::new(symbol, Decimal::from(n * 10))).collect();
+            for data in &market_data {
+                let order = Order {
+                    symbol: data.symbol.clone(),
+                    quantity: dec!(3),
+                    order_type: OrderType::Market,
+                    time_in_force: TimeInForce::Gtc,
+                };
+                portfolio.execute_order(&order, data);
+            }
+
+            [
+                portfolio.to_string(),
+                portfolio.summary(&market_data),
+                portfolio.holdings_table(&market_data),
+                format!("{:?}", portfolio.pl_by_symbol(&market_data)),
+                serde_json::to_string(&portfolio).unwrap(),
+                serde_json::to_string(&portfolio.snapshot()).unwrap(),
+            ]
+            .join("\n")
+        };
+
+        let output = run();
+        assert_eq!(output, run());
+        assert!(output.find("AAPL").unwrap() < output.find("AMZN").unwrap());
+        assert!(output.find("MSFT").unwrap() < output.find("TSLA").unwrap());
+    }
+}
@@ -0,0 +1,96 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::market_data::{MarketData, MarketSnapshot};
+use crate::money::round_money;
+use crate::portfolio::Portfolio;
+
+// Exports follow the market data CSV conventions: comma separated, no header,
+// one row per line, money rounded with round_money. A field holding a comma or
+// quote is quoted, with its quotes doubled, as the market data reader expects.
+impl Portfolio {
+    // Writes symbol,quantity,avg_cost,value rows sorted by symbol. The value is
+    // left empty for a holding with no market data.
+    pub fn export_holdings_csv(&self, file_path: &str, current_market_data: &[MarketData]) -> io::Result<()> {
+        let snapshot = MarketSnapshot::new(current_market_data);
+        let mut writer = BufWriter::new(File::create(file_path)?);
+
+        for (symbol, position) in &self.holdings {
+            if position.quantity.is_zero() {
+                continue;
+            }
+
+            let value = snapshot
+                .price(symbol)
+                .map(|price| round_money(price * position.quantity).to_string())
+                .unwrap_or_default();
+            writeln!(writer, "{},{},{},{}", quote(symbol), position.quantity, round_money(position.avg_cost), value)?;
+        }
+
+        writer.flush()
+    }
+
+    // Writes sequence,symbol,quantity,price,commission rows in execution order
+    pub fn export_transactions_csv(&self, file_path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(file_path)?);
+
+        for trade in self.transactions() {
+            writeln!(
+                writer,
+                "{},{},{},{},{}",
+                trade.sequence,
+                quote(&trade.symbol),
+                trade.quantity,
+                round_money(trade.price),
+                round_money(trade.commission)
+            )?;
+        }
+
+        writer.flush()
+    }
+}
+
+fn quote(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::order::{Order, OrderType, TimeInForce};
+    use crate::portfolio::CommissionModel;
+
+    #[test]
+    fn exports_round_money_and_quote_symbols_with_commas() {
+        let mut portfolio = Portfolio::new(dec!(10000));
+        portfolio.commission = CommissionModel::Percentage(dec!(0.001));
+        let order = Order {
+            symbol: "BRK,A".to_string(),
+            quantity: dec!(3),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        };
+        portfolio.execute_order(&order, &MarketData::new("BRK,A", dec!(100.1234)));
+
+        let dir = std::env::temp_dir();
+        let holdings = dir.join(format!("holdings_{}.csv", std::process::id()));
+        let transactions = dir.join(format!("transactions_{}.csv", std::process::id()));
+        portfolio.export_holdings_csv(holdings.to_str().unwrap(), &[MarketData::new("BRK,A", dec!(101))]).unwrap();
+        portfolio.export_transactions_csv(transactions.to_str().unwrap()).unwrap();
+        let holdings_csv = std::fs::read_to_string(&holdings).unwrap();
+        let transactions_csv = std::fs::read_to_string(&transactions).unwrap();
+        std::fs::remove_file(&holdings).unwrap();
+        std::fs::remove_file(&transactions).unwrap();
+
+        assert_eq!(holdings_csv, "\"BRK,A\",3,100.12,303\n");
+        assert_eq!(transactions_csv, "1,\"BRK,A\",3,100.12,0.30\n");
+        assert_eq!(quote("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
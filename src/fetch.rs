@@ -0,0 +1,55 @@
+use std::error::Error;
+use std::fmt;
+
+use reqwest::StatusCode;
+
+use crate::market_data::{check_quotes, InvalidPricePolicy, LoadError, MarketData};
+
+#[derive(Debug)]
+pub enum FetchError {
+    Request(reqwest::Error), // Connection or transport failure
+    Status(StatusCode), // Server answered with a non-success status
+    Json(serde_json::Error), // Body was not a valid quote list
+    InvalidPrice(LoadError), // A quote whose price, bid or ask isn't strictly positive
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Request(err) => write!(f, "request failed: {}", err),
+            FetchError::Status(status) => write!(f, "server returned {}", status),
+            FetchError::Json(err) => write!(f, "malformed market data: {}", err),
+            FetchError::InvalidPrice(err) => write!(f, "invalid market data: {}", err),
+        }
+    }
+}
+
+impl Error for FetchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FetchError::Request(err) => Some(err),
+            FetchError::Status(_) => None,
+            FetchError::Json(err) => Some(err),
+            FetchError::InvalidPrice(err) => Some(err),
+        }
+    }
+}
+
+// GETs a JSON quote list in the same shape load_market_data_json reads, with
+// the same price checks
+pub async fn fetch_market_data(url: &str) -> Result<Vec<MarketData>, FetchError> {
+    fetch_market_data_with_policy(url, InvalidPricePolicy::default()).await
+}
+
+pub async fn fetch_market_data_with_policy(url: &str, invalid_prices: InvalidPricePolicy) -> Result<Vec<MarketData>, FetchError> {
+    let response = reqwest::get(url).await.map_err(FetchError::Request)?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(FetchError::Status(status));
+    }
+
+    let body = response.text().await.map_err(FetchError::Request)?;
+    let quotes = serde_json::from_str(&body).map_err(FetchError::Json)?;
+    check_quotes(quotes, invalid_prices).map_err(FetchError::InvalidPrice)
+}
@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+
+// Average of the last period prices, or None with fewer prices than that
+pub fn sma(prices: &[f32], period: usize) -> Option<f32> {
+    if period == 0 || prices.len() < period {
+        return None;
+    }
+
+    let window = &prices[prices.len() - period..];
+    Some(window.iter().sum::<f32>() / period as f32)
+}
+
+// Simple moving average fed one price at a time, keeping a running sum so each
+// update is constant time
+#[derive(Debug, Clone)]
+pub struct SmaState {
+    period: usize,
+    window: VecDeque<f32>,
+    sum: f32,
+}
+
+impl SmaState {
+    pub fn new(period: usize) -> Self {
+        SmaState {
+            period,
+            window: VecDeque::with_capacity(period),
+            sum: 0.0,
+        }
+    }
+
+    // Adds the next price and returns the updated average
+    pub fn push(&mut self, price: f32) -> Option<f32> {
+        if self.period == 0 {
+            return None;
+        }
+
+        self.window.push_back(price);
+        self.sum += price;
+        if self.window.len() > self.period {
+            self.sum -= self.window.pop_front().unwrap_or_default();
+        }
+
+        self.value()
+    }
+
+    // Current average, or None until period prices have been pushed
+    pub fn value(&self) -> Option<f32> {
+        if self.period == 0 || self.window.len() < self.period {
+            None
+        } else {
+            Some(self.sum / self.period as f32)
+        }
+    }
+}
+
+// Wilder's relative strength index over the given prices, from 0 (only
+// losses) to 100 (only gains). Needs period + 1 prices for the first value.
+pub fn rsi(prices: &[f32], period: usize) -> Option<f32> {
+    let mut state = RsiState::new(period);
+    prices.iter().fold(None, |_, &price| state.push(price))
+}
+
+// Relative strength index fed one price at a time. The first average gain and
+// loss are plain means over period changes; after that each new change is
+// blended in with Wilder's smoothing.
+#[derive(Debug, Clone)]
+pub struct RsiState {
+    period: usize,
+    previous: Option<f32>,
+    changes: usize, // Price changes seen, counted up to period
+    avg_gain: f32,
+    avg_loss: f32,
+}
+
+impl RsiState {
+    pub fn new(period: usize) -> Self {
+        RsiState {
+            period,
+            previous: None,
+            changes: 0,
+            avg_gain: 0.0,
+            avg_loss: 0.0,
+        }
+    }
+
+    // Adds the next price and returns the updated RSI
+    pub fn push(&mut self, price: f32) -> Option<f32> {
+        if self.period == 0 {
+            return None;
+        }
+
+        if let Some(previous) = self.previous.replace(price) {
+            let change = price - previous;
+            let (gain, loss) = (change.max(0.0), (-change).max(0.0));
+            let period = self.period as f32;
+
+            if self.changes < self.period {
+                self.changes += 1;
+                self.avg_gain += gain / period;
+                self.avg_loss += loss / period;
+            } else {
+                self.avg_gain = (self.avg_gain * (period - 1.0) + gain) / period;
+                self.avg_loss = (self.avg_loss * (period - 1.0) + loss) / period;
+            }
+        }
+
+        self.value()
+    }
+
+    // Current RSI, or None until period changes have been seen. A window with
+    // no movement at all reads as a neutral 50.
+    pub fn value(&self) -> Option<f32> {
+        if self.period == 0 || self.changes < self.period {
+            return None;
+        }
+
+        if self.avg_loss == 0.0 {
+            return Some(if self.avg_gain == 0.0 { 50.0 } else { 100.0 });
+        }
+
+        let relative_strength = self.avg_gain / self.avg_loss;
+        Some(100.0 - 100.0 / (1.0 + relative_strength))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sma_needs_a_full_window() {
+        assert_eq!(sma(&[1.0, 2.0], 3), None);
+        assert_eq!(sma(&[1.0, 2.0, 3.0], 0), None);
+        assert_eq!(sma(&[1.0, 2.0, 3.0, 4.0, 5.0], 3), Some(4.0));
+
+        let mut state = SmaState::new(3);
+        let averages: Vec<Option<f32>> = [1.0, 2.0, 3.0, 4.0, 5.0].into_iter().map(|price| state.push(price)).collect();
+        assert_eq!(averages, vec![None, None, Some(2.0), Some(3.0), Some(4.0)]);
+        assert_eq!(SmaState::new(0).push(1.0), None);
+    }
+
+    #[test]
+    fn rsi_is_100_for_only_gains_and_0_for_only_losses() {
+        let rising: Vec<f32> = (1..=15).map(|price| price as f32).collect();
+        let falling: Vec<f32> = rising.iter().rev().copied().collect();
+
+        assert_eq!(rsi(&rising, 14), Some(100.0));
+        assert_eq!(rsi(&falling, 14), Some(0.0));
+        assert_eq!(rsi(&rising[..14], 14), None);
+    }
+
+    #[test]
+    fn rsi_matches_wilders_smoothing_by_hand() {
+        // Changes of +2 and -1 average 1 and 0.5, an RSI of 66.67; then +3
+        // smooths them to 2 and 0.25, an RSI of 88.89
+        let prices = [10.0, 12.0, 11.0, 14.0];
+        assert!((rsi(&prices[..3], 2).unwrap() - 200.0 / 3.0).abs() < 1e-3);
+        assert!((rsi(&prices, 2).unwrap() - 800.0 / 9.0).abs() < 1e-3);
+
+        let mut state = RsiState::new(2);
+        let streamed = prices.iter().fold(None, |_, &price| state.push(price));
+        assert_eq!(streamed, rsi(&prices, 2));
+    }
+}
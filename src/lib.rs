@@ -0,0 +1,62 @@
+mod alerts;
+mod backtest;
+mod bars;
+mod costs;
+mod display;
+mod export;
+mod fetch;
+mod indicators;
+mod manager;
+mod margin;
+mod market_data;
+mod metrics;
+mod money;
+mod monte_carlo;
+mod order;
+mod portfolio;
+mod rebalance;
+#[cfg(feature = "server")]
+mod server;
+mod sizing;
+mod snapshot;
+mod statement;
+mod strategy;
+mod stream;
+mod synthetic;
+mod watchlist;
+
+pub use alerts::{Alert, Alerts, Direction, TriggeredAlert};
+pub use backtest::{
+    backtest, backtest_bars, backtest_batch, backtest_with_options, compare_to_buy_and_hold,
+    BacktestConfig, BacktestOptions, BacktestResult, Comparison,
+};
+pub use bars::{load_bars, read_bars, replay, replay_with, Ohlc};
+pub use costs::{CostReport, SymbolCosts};
+pub use display::Summary;
+pub use fetch::{fetch_market_data, fetch_market_data_with_policy, FetchError};
+pub use indicators::{rsi, sma, RsiState, SmaState};
+pub use manager::{PortfolioManager, PortfolioReport};
+pub use market_data::{
+    check_quotes, find_fill_price, find_market_price, load_dividends, load_market_data, load_market_data_auto,
+    load_market_data_auto_with_options, load_market_data_json, load_market_data_json_with_policy,
+    load_market_data_many, load_market_data_with_options, normalize_symbol, load_symbol_meta, parse_price,
+    read_market_data, BookLevel, CsvOptions, DividendEvent,
+    InvalidPricePolicy, LoadError, MarketData, MarketSnapshot, OrderBook, SymbolMeta,
+};
+pub use metrics::{correlation_matrix, MIN_VAR_RETURNS};
+pub use money::{round_money, MONEY_DECIMAL_PLACES, MONEY_ROUNDING};
+pub use monte_carlo::{monte_carlo, MonteCarloResult};
+pub use order::{Order, OrderError, OrderGroup, OrderId, OrderStatus, OrderType, TimeInForce};
+pub use portfolio::{
+    CommissionModel, Lot, LotMethod, OpenOrder, OrderPreview, Portfolio, Position, RealizedGain, Slippage, Trade,
+    TradingSession, INITIAL_CASH,
+};
+#[cfg(feature = "server")]
+pub use server::{router, serve, SharedPortfolio};
+pub use sizing::{kelly_fraction, kelly_quantity, volatility_target_quantity};
+pub use snapshot::{ChangeKind, HoldingChange, PortfolioDiff, PortfolioSnapshot};
+pub use statement::LedgerEntry;
+pub use strategy::{BuyAndHold, MovingAverageCrossover, Strategy};
+pub use stream::{run_stream, run_stream_with_strategy};
+pub use synthetic::generate_random_walk;
+pub use watchlist::Watchlist;
@@ -1,153 +1,260 @@
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+mod color;
+mod repl;
 
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+#[cfg(feature = "server")]
+use std::thread;
 
-const INITIAL_CASH: f32 = 10000.0;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use stock_trading_simulator::{
+    fetch_market_data, load_bars, load_dividends, load_market_data_auto, replay, round_money, run_stream, MarketData,
+    MarketSnapshot, Portfolio, Watchlist, INITIAL_CASH,
+};
+#[cfg(feature = "server")]
+use stock_trading_simulator::serve;
 
-#[derive(Debug)]
-struct MarketData {
-    symbol: String,
-    price: f32,
-}
-
-#[derive(Debug)]
-enum OrderType {
-    Market,
-    Limit(f32),
-}
+use repl::{parse_command, Command, USAGE};
 
-#[derive(Debug)]
-struct Order {
-    symbol: String,
-    quantity: i32,
-    order_type: OrderType,
-}
+// Fee charged per simulated day on the market value of each short position
+const DAILY_BORROW_FEE_RATE: Decimal = dec!(0.0001);
+// Annual interest paid on uninvested cash
+const CASH_INTEREST_RATE: Decimal = dec!(0.02);
 
-struct Portfolio {
-    cash: f32,
-    holdings: HashMap<String, i32>, // Symbol to quantity mapping
-}
+fn main() {
+    // Library warnings are shown by default; set RUST_LOG=info to follow every order
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
 
-impl Portfolio {
-    fn execute_order(&mut self, order: &Order, market_price: f32) {
-        match order.order_type {
-            OrderType::Market => {
-                // Execute market order at the current market price
-                self.process_order(order, market_price);
-            },
-            OrderType::Limit(limit_price) => {
-                if (order.quantity > 0 && market_price <= limit_price) || (order.quantity < 0 && market_price >= limit_price) {
-                    // Execute limit order if the market price is favorable
-                    self.process_order(order, limit_price);
-                }
-                // Else, do not execute the order as the limit condition is not met
-            }
-        }
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    // --serve ADDR, e.g. --serve 127.0.0.1:3000, shows the portfolio over HTTP
+    // while the session runs
+    let serve_addr = take_option(&mut args, "--serve");
+    #[cfg(not(feature = "server"))]
+    if serve_addr.is_some() {
+        eprintln!("--serve needs a build with the server feature");
+        std::process::exit(2);
     }
 
-    fn process_order(&mut self, order: &Order, execution_price: f32) {
-        let total_order_value = execution_price * order.quantity.abs() as f32;
+    // --color and --no-color override coloring gains and losses, which is
+    // otherwise on only when printing to a terminal
+    let (flags, args): (Vec<String>, Vec<String>) =
+        args.into_iter().partition(|arg| arg == "--color" || arg == "--no-color" || arg == "--stream");
+    let color = color::enabled(flags.iter().rfind(|flag| *flag != "--stream").map(|flag| flag == "--color"));
+    let paint = move |value, text| color::paint(value, text, color);
 
-        if order.quantity > 0 {
-            // Buying stocks
-            if self.cash >= total_order_value {
-                *self.holdings.entry(order.symbol.clone()).or_insert(0) += order.quantity;
-                self.cash -= total_order_value;
-            } else {
-                println!("Not enough cash to execute buy order.");
-            }
-        } else if order.quantity < 0 {
-            // Selling stocks
-            let current_holding = self.holdings.entry(order.symbol.clone()).or_insert(0);
-
-            if *current_holding >= -order.quantity {
-                *current_holding += order.quantity; // Deducting as quantity is negative
-                self.cash += total_order_value;
-            } else {
-                println!("Not enough shares to execute sell order.");
-            }
-        }
+    // --stream reads quotes from stdin as they arrive, as in `tail -f feed.csv | sim --stream`,
+    // filling the resting orders of a portfolio saved from an earlier session
+    if flags.iter().any(|flag| flag == "--stream") {
+        let mut portfolio = match args.first() {
+            Some(path) => Portfolio::load_state(path).expect("Failed to load portfolio"),
+            None => Portfolio::new(INITIAL_CASH),
+        };
+        let snapshot = run_stream(&mut portfolio, io::stdin().lock()).expect("Failed to read quotes");
+        print_session_end(&portfolio, &snapshot.quotes(), color);
+        return;
     }
 
-    fn calculate_profit_loss(&self, current_market_data: &[MarketData]) -> f32 {
-        let mut total_value = 0.0;
-
-        // Calculate the total value of the portfolio based on current market prices
-        for data in current_market_data {
-            if let Some(&quantity) = self.holdings.get(&data.symbol) {
-                total_value += data.price * quantity as f32;
+    // Initialize portfolio with some initial cash, shared with the server if
+    // there is one as its SharedPortfolio. Only the commands that use the
+    // portfolio lock it, and only while they do, so a slow replay or waiting for
+    // input never keeps the server from reading it.
+    let shared = Arc::new(Mutex::new(Portfolio::new(INITIAL_CASH)));
+    #[cfg(feature = "server")]
+    if let Some(addr) = serve_addr {
+        let shared = Arc::clone(&shared);
+        println!("Serving the portfolio on http://{}", addr);
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to start async runtime");
+            if let Err(err) = runtime.block_on(serve(&addr, shared)) {
+                eprintln!("Server on {} stopped: {}", addr, err);
             }
+        });
+    }
+
+    // Load market data from a URL or file given on the command line, or the bundled CSV
+    let market_data = match args.into_iter().next() {
+        Some(url) if url.starts_with("http://") || url.starts_with("https://") => {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to start async runtime");
+            runtime.block_on(fetch_market_data(&url)).expect("Failed to fetch market data")
         }
+        Some(path) => load_market_data_auto(&path).expect("Failed to load market data"),
+        None => load_market_data_auto("market_data.csv").expect("Failed to load market data"),
+    };
+    let snapshot = MarketSnapshot::new(&market_data);
 
-        // Total portfolio value - initial cash gives profit or loss
-        total_value + self.cash - INITIAL_CASH
-    }
-}
+    // Dividends listed next to the market data are paid out on request
+    let dividends = if Path::new("dividends.csv").exists() {
+        load_dividends("dividends.csv").expect("Failed to load dividends")
+    } else {
+        Vec::new()
+    };
+
+    println!("{}", USAGE);
+    let mut day = 0;
+    let mut watchlist = Watchlist::new();
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush().expect("Failed to flush stdout");
 
-fn load_market_data(file_path: &str) -> io::Result<Vec<MarketData>> {
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
-    let mut data = Vec::new();
-
-    for line in reader.lines() {
-        let line = line?;
-        let parts: Vec<&str> = line.split(',').collect();
-        if parts.len() == 2 {
-            let symbol = parts[0].to_string();
-            let price = parts[1].parse::<f32>().unwrap_or(0.0);
-            data.push(MarketData { symbol, price });
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).expect("Failed to read input") == 0 {
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match parse_command(&line) {
+            Ok(Command::Order(order)) => {
+                if let Some(quote) = snapshot.quote(&order.symbol) {
+                    let (id, status) = lock(&shared).execute_order(&order, quote);
+                    println!("Order {}: {}", id, status);
+                } else {
+                    println!("Market data not found for {}", order.symbol);
+                }
+            }
+            Ok(Command::Preview(order)) => {
+                if let Some(quote) = snapshot.quote(&order.symbol) {
+                    let preview = lock(&shared).preview_order(&order, quote);
+                    match preview.fill_price {
+                        Some(price) => println!(
+                            "Would be {}: {} @ ${:.2}, commission ${:.2}, cash {:+.2}, holding {}",
+                            preview.status,
+                            preview.quantity,
+                            round_money(price),
+                            round_money(preview.commission),
+                            round_money(preview.cash_change),
+                            preview.position
+                        ),
+                        None => println!("Would be {}", preview.status),
+                    }
+                } else {
+                    println!("Market data not found for {}", order.symbol);
+                }
+            }
+            Ok(Command::Liquidate) => {
+                let results = lock(&shared).liquidate(&market_data);
+                for (order, status) in results {
+                    println!("{} {}: {}", order.quantity, order.symbol, status);
+                }
+            }
+            Ok(Command::Reduce(fraction)) => {
+                let results = lock(&shared).reduce_all(fraction, &market_data);
+                for (order, status) in results {
+                    println!("{} {}: {}", order.quantity, order.symbol, status);
+                }
+            }
+            Ok(Command::Status) => print_status(&lock(&shared), &market_data, color),
+            Ok(Command::Statement) => println!("{}", lock(&shared).statement_with(&market_data, paint)),
+            Ok(Command::Holdings) => println!("{}", lock(&shared).holdings_table_with(&market_data, paint)),
+            Ok(Command::Watch(symbol)) => {
+                if !watchlist.add(&symbol) {
+                    println!("Already watching {}", symbol);
+                }
+            }
+            Ok(Command::Unwatch(symbol)) => {
+                if !watchlist.remove(&symbol) {
+                    println!("Not watching {}", symbol);
+                }
+            }
+            Ok(Command::Watchlist) => watchlist.show(&market_data),
+            Ok(Command::Day) => {
+                day += 1;
+                let mut portfolio = lock(&shared);
+                let fees = portfolio.accrue_borrow_fees(&market_data, DAILY_BORROW_FEE_RATE);
+                let interest = portfolio.accrue_interest(CASH_INTEREST_RATE, 1);
+                println!("Day {}: borrow fees ${:.2}, interest ${:.2}", day, round_money(fees), round_money(interest));
+                for (id, status) in portfolio.end_of_day() {
+                    println!("Order {}: {}", id, status);
+                }
+            }
+            Ok(Command::Dividends) => {
+                let mut portfolio = lock(&shared);
+                for dividend in &dividends {
+                    println!("Dividend of ${:.2} per share on {} ({})", round_money(dividend.per_share), dividend.symbol, dividend.date);
+                    portfolio.apply_dividend(&dividend.symbol, dividend.per_share, snapshot.price(&dividend.symbol));
+                }
+                println!("Cash Balance after dividends: ${:.2}", round_money(portfolio.cash));
+            }
+            Ok(Command::Reinvest(reinvest)) => {
+                lock(&shared).reinvest_dividends = reinvest;
+                println!("Dividend reinvestment {}", if reinvest { "on" } else { "off" });
+            }
+            Ok(Command::Replay { path, speed }) => match load_bars(&path) {
+                Ok(bars) => replay(bars.into_iter().flatten().collect(), speed, |bar| {
+                    println!(
+                        "{} {} O {:.2} H {:.2} L {:.2} C {:.2} V {}",
+                        bar.time,
+                        bar.symbol,
+                        round_money(bar.open),
+                        round_money(bar.high),
+                        round_money(bar.low),
+                        round_money(bar.close),
+                        bar.volume
+                    );
+                }),
+                Err(err) => println!("Failed to load bars from {}: {}", path, err),
+            },
+            Ok(Command::Quit) => break,
+            Err(err) => println!("{}\n{}", err, USAGE),
         }
     }
 
-    Ok(data)
+    print_session_end(&lock(&shared), &market_data, color);
 }
 
+// Removes `name value` from the arguments, returning the value
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == name)?;
+    args.remove(index);
+    if index == args.len() {
+        eprintln!("{} needs a value", name);
+        std::process::exit(2);
+    }
+    Some(args.remove(index))
+}
 
-fn find_market_price(market_data: &[MarketData], symbol: &str) -> Option<f32> {
-    market_data.iter().find(|&data| data.symbol == symbol).map(|data| data.price)
+// A server that panicked while reading leaves the portfolio intact, so a
+// poisoned lock is used as is
+fn lock(portfolio: &Mutex<Portfolio>) -> std::sync::MutexGuard<'_, Portfolio> {
+    portfolio.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
+// The trades made and how the portfolio stands at current prices
+fn print_session_end(portfolio: &Portfolio, market_data: &[MarketData], color: bool) {
+    println!("Transactions:");
+    for trade in portfolio.transactions() {
+        println!(
+            "  #{} {} {} @ ${:.2} (commission ${:.2})",
+            trade.sequence,
+            trade.symbol,
+            trade.quantity,
+            round_money(trade.price),
+            round_money(trade.commission)
+        );
+    }
+    println!("{}", portfolio.cost_report());
+    println!("{}", portfolio.summary_report(market_data).to_string_with(|value, text| color::paint(value, text, color)));
+}
 
-fn main() {
-    // Initialize portfolio with some initial cash
-    let mut portfolio = Portfolio {
-        cash: INITIAL_CASH,
-        holdings: HashMap::new(),
-    };
+fn print_status(portfolio: &Portfolio, market_data: &[MarketData], color: bool) {
+    println!("{}", portfolio.summary(market_data));
+    println!("Buying power: ${:.2}", round_money(portfolio.buying_power(market_data)));
+    println!("Realized profit or loss: {}", color::money(portfolio.realized_pl(), color));
+    println!("Current profit or loss: {}", color::money(portfolio.calculate_profit_loss(market_data), color));
 
-    // TODO::Load data from API in async
-    let market_data = load_market_data("market_data.csv").expect("Failed to load market data");
-
-    // TODO::implement user interface to make orders
-    let orders = vec![
-        Order {
-            symbol: "AAPL".to_string(),
-            quantity: 1,
-            order_type: OrderType::Market,
-        },
-        Order {
-            symbol: "MSFT".to_string(),
-            quantity: 2,
-            order_type: OrderType::Limit(280.0),
-        },
-        Order {
-            symbol: "AAPL".to_string(),
-            quantity: -1,
-            order_type: OrderType::Market,
-        },
-    ];
-
-    for order in orders {
-        println!("Processing order: {:?}", order);
-        if let Some(market_price) = find_market_price(&market_data, &order.symbol) {
-            portfolio.execute_order(&order, market_price);
-        } else {
-            println!("Market data not found for {}", order.symbol);
-        }
-        println!("Current Holdings: {:?}", portfolio.holdings);
-        println!("Current Cash Balance: ${:.2}", portfolio.cash);
-        let profit_loss = portfolio.calculate_profit_loss(&market_data);
-        println!("Current profit or loss: ${:.2}\n", profit_loss);
+    println!("Open orders:");
+    for open_order in portfolio.open_orders() {
+        println!("  {} {:?}", open_order.id, open_order.order);
     }
 }
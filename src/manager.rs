@@ -0,0 +1,152 @@
+use std::cmp::Reverse;
+
+use rust_decimal::Decimal;
+
+use crate::market_data::{MarketData, MarketSnapshot};
+use crate::money::round_money;
+use crate::order::{Order, OrderId, OrderStatus};
+use crate::portfolio::Portfolio;
+
+// Named portfolios run side by side against the same market data. Each keeps
+// its own cash, holdings and transaction log; a snapshot is indexed once and
+// shared by all of them.
+#[derive(Debug, Clone, Default)]
+pub struct PortfolioManager {
+    portfolios: Vec<(String, Portfolio)>, // In the order they were added
+}
+
+// One row of PortfolioManager::report
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortfolioReport {
+    pub name: String,
+    pub market_value: Decimal,
+    pub profit_loss: Decimal,
+    pub percent_return: Option<f32>,
+    pub trades: usize,
+}
+
+impl PortfolioManager {
+    pub fn new() -> Self {
+        PortfolioManager::default()
+    }
+
+    // Returns false, leaving the existing one in place, if the name is taken
+    pub fn add(&mut self, name: &str, portfolio: Portfolio) -> bool {
+        if self.get(name).is_some() {
+            return false;
+        }
+
+        self.portfolios.push((name.to_string(), portfolio));
+        true
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Portfolio> {
+        self.portfolios.iter().find(|(existing, _)| existing == name).map(|(_, portfolio)| portfolio)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Portfolio> {
+        self.portfolios.iter_mut().find(|(existing, _)| existing == name).map(|(_, portfolio)| portfolio)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.portfolios.iter().map(|(name, _)| name.as_str())
+    }
+
+    // Routes the order to the named portfolio, None if there is no such portfolio
+    pub fn execute_order(&mut self, name: &str, order: &Order, quote: &MarketData) -> Option<(OrderId, OrderStatus)> {
+        self.get_mut(name).map(|portfolio| portfolio.execute_order(order, quote))
+    }
+
+    // Portfolio::apply_snapshot for every portfolio, returning each one's
+    // resting order results under its name
+    pub fn apply_snapshot(&mut self, timestamp: i64, market_data: &[MarketData]) -> Vec<(String, Vec<(OrderId, OrderStatus)>)> {
+        let snapshot = MarketSnapshot::new(market_data);
+
+        self.portfolios
+            .iter_mut()
+            .map(|(name, portfolio)| {
+                let results = portfolio.process_pending(&snapshot);
+                portfolio.mark_equity(timestamp, market_data);
+                (name.clone(), results)
+            })
+            .collect()
+    }
+
+    // Every portfolio valued at the given market data, best profit first
+    pub fn report(&self, current_market_data: &[MarketData]) -> Vec<PortfolioReport> {
+        let mut report: Vec<PortfolioReport> = self
+            .portfolios
+            .iter()
+            .map(|(name, portfolio)| PortfolioReport {
+                name: name.clone(),
+                market_value: portfolio.market_value(current_market_data),
+                profit_loss: portfolio.calculate_profit_loss(current_market_data),
+                percent_return: portfolio.percent_return(current_market_data),
+                trades: portfolio.transactions().len(),
+            })
+            .collect();
+
+        report.sort_by_key(|row| Reverse(row.profit_loss));
+        report
+    }
+
+    // The report as an aligned table, one portfolio per line
+    pub fn report_table(&self, current_market_data: &[MarketData]) -> String {
+        let report = self.report(current_market_data);
+        let width = report.iter().map(|row| row.name.len()).max().unwrap_or(0).max("Portfolio".len());
+
+        let mut lines = vec![format!("{:<width$} {:>14} {:>14} {:>9} {:>7}", "Portfolio", "Value", "P/L", "Return", "Trades")];
+        for row in report {
+            let percent = row.percent_return.map_or("n/a".to_string(), |value| format!("{:.2}%", value * 100.0));
+            lines.push(format!(
+                "{:<width$} {:>14.2} {:>14.2} {:>9} {:>7}",
+                row.name,
+                round_money(row.market_value),
+                round_money(row.profit_loss),
+                percent,
+                row.trades
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::order::{OrderType, TimeInForce};
+
+    fn order(quantity: Decimal, order_type: OrderType) -> Order {
+        Order {
+            symbol: "AAPL".to_string(),
+            quantity,
+            order_type,
+            time_in_force: TimeInForce::Gtc,
+        }
+    }
+
+    #[test]
+    fn portfolios_trade_independently() {
+        let mut manager = PortfolioManager::new();
+        assert!(manager.add("active", Portfolio::new(dec!(1000))));
+        assert!(manager.add("passive", Portfolio::new(dec!(1000))));
+        assert!(!manager.add("active", Portfolio::new(dec!(5))));
+
+        manager.execute_order("active", &order(dec!(5), OrderType::Market), &MarketData::new("AAPL", dec!(100)));
+        manager.execute_order("passive", &order(dec!(5), OrderType::Limit(dec!(90))), &MarketData::new("AAPL", dec!(100)));
+        assert_eq!(manager.execute_order("missing", &order(dec!(1), OrderType::Market), &MarketData::new("AAPL", dec!(100))), None);
+
+        // The snapshot fills the passive portfolio's resting limit only
+        let results = manager.apply_snapshot(1, &[MarketData::new("AAPL", dec!(90))]);
+        assert!(results[0].1.is_empty());
+        assert_eq!(results[1].1.len(), 1);
+
+        let report = manager.report(&[MarketData::new("AAPL", dec!(120))]);
+        assert_eq!(report[0].name, "passive");
+        assert_eq!(report[0].profit_loss, dec!(150));
+        assert_eq!(report[1].profit_loss, dec!(100));
+        assert_eq!(manager.get("active").unwrap().cash, dec!(500));
+    }
+}
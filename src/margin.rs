@@ -0,0 +1,144 @@
+use rust_decimal::Decimal;
+
+use crate::market_data::{find_market_price, MarketData};
+use crate::money::round_money;
+use crate::portfolio::Portfolio;
+
+impl Portfolio {
+    // Amount of equity the account is short of the maintenance requirement,
+    // which is maintenance_pct (e.g. 0.25 for 25%) of the market value of the
+    // positions held while money is borrowed. None for a cash account, when
+    // nothing is borrowed, or when equity still covers the requirement.
+    pub fn check_margin_call(&self, current_market_data: &[MarketData], maintenance_pct: Decimal) -> Option<Decimal> {
+        if self.leverage <= Decimal::ONE || self.margin_used().is_zero() {
+            return None;
+        }
+
+        let positions_value: Decimal = self
+            .holdings
+            .iter()
+            .filter_map(|(symbol, position)| Some(find_market_price(current_market_data, symbol)? * position.quantity.abs()))
+            .sum();
+
+        let shortfall = maintenance_pct * positions_value - self.market_value(current_market_data);
+        (shortfall > Decimal::ZERO).then_some(shortfall)
+    }
+
+    // Charges one day's borrow fee on every short position: daily_rate (e.g.
+    // 0.0001 for 0.01%) of the short's market value. Longs, flat positions and
+    // shorts without a quote cost nothing. Returns the total charged.
+    pub fn accrue_borrow_fees(&mut self, current_market_data: &[MarketData], daily_rate: Decimal) -> Decimal {
+        let fees: Decimal = self
+            .holdings
+            .iter()
+            .filter(|(_, position)| position.quantity < Decimal::ZERO)
+            .filter_map(|(symbol, position)| Some(daily_rate * position.quantity.abs() * find_market_price(current_market_data, symbol)?))
+            .sum();
+        let fees = round_money(fees);
+
+        self.cash -= fees;
+        fees
+    }
+
+    // Credits simple interest at annual_rate (e.g. 0.05 for 5%) over a 365-day
+    // year on a positive cash balance. A negative balance borrowed on margin is
+    // charged margin_interest_rate instead. Returns the change to cash.
+    pub fn accrue_interest(&mut self, annual_rate: Decimal, days: i64) -> Decimal {
+        if days <= 0 {
+            return Decimal::ZERO;
+        }
+
+        let rate = if self.cash < Decimal::ZERO { self.margin_interest_rate } else { annual_rate };
+
+        let interest = round_money(self.cash * rate * Decimal::from(days) / Decimal::from(365));
+        self.cash += interest;
+        interest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::order::{Order, OrderError, OrderStatus, OrderType, TimeInForce};
+
+    fn market_order(quantity: Decimal) -> Order {
+        Order {
+            symbol: "AAPL".to_string(),
+            quantity,
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        }
+    }
+
+    fn margin_account() -> Portfolio {
+        let mut portfolio = Portfolio::new(dec!(10000));
+        portfolio.leverage = dec!(2);
+        portfolio
+    }
+
+    #[test]
+    fn a_buy_beyond_cash_fills_within_twice_the_buying_power() {
+        let mut portfolio = margin_account();
+        let market_data = [MarketData::new("AAPL", dec!(100))];
+        assert_eq!(portfolio.buying_power(&market_data), dec!(20000));
+
+        let (_, status) = portfolio.clone().execute_order(&market_order(dec!(201)), &market_data[0]);
+        assert!(matches!(status, OrderStatus::Rejected(OrderError::InsufficientCash { .. })));
+
+        let (_, status) = portfolio.execute_order(&market_order(dec!(150)), &market_data[0]);
+        assert_eq!(status, OrderStatus::Filled);
+        assert_eq!(portfolio.cash, dec!(-5000));
+        assert_eq!(portfolio.margin_used(), dec!(5000));
+        assert_eq!(portfolio.buying_power(&market_data), dec!(5000));
+    }
+
+    #[test]
+    fn a_price_drop_on_a_leveraged_long_calls_for_the_shortfall() {
+        let mut portfolio = margin_account();
+        portfolio.execute_order(&market_order(dec!(200)), &MarketData::new("AAPL", dec!(100)));
+        assert_eq!(portfolio.check_margin_call(&[MarketData::new("AAPL", dec!(100))], dec!(0.25)), None);
+
+        // Positions worth 12000 need 3000 of equity, and only 2000 is left
+        assert_eq!(portfolio.check_margin_call(&[MarketData::new("AAPL", dec!(60))], dec!(0.25)), Some(dec!(1000)));
+
+        let mut cash_account = Portfolio::new(dec!(10000));
+        cash_account.execute_order(&market_order(dec!(100)), &MarketData::new("AAPL", dec!(100)));
+        assert_eq!(cash_account.check_margin_call(&[MarketData::new("AAPL", dec!(1))], dec!(0.25)), None);
+    }
+
+    #[test]
+    fn borrow_fees_accrue_on_shorts_each_day() {
+        let mut portfolio = Portfolio::new(dec!(10000));
+        portfolio.allow_shorting = true;
+        let market_data = [MarketData::new("AAPL", dec!(100)), MarketData::new("MSFT", dec!(200))];
+        portfolio.execute_order(&market_order(dec!(-10)), &market_data[0]);
+        portfolio.execute_order(&Order { symbol: "MSFT".to_string(), ..market_order(dec!(5)) }, &market_data[1]);
+        assert_eq!(portfolio.cash, dec!(10000));
+
+        // 0.1% a day of the 1000 short; the long costs nothing
+        for day in 1..=3 {
+            assert_eq!(portfolio.accrue_borrow_fees(&market_data, dec!(0.001)), dec!(1));
+            assert_eq!(portfolio.cash, dec!(10000) - Decimal::from(day));
+        }
+
+        portfolio.execute_order(&market_order(dec!(10)), &market_data[0]);
+        assert_eq!(portfolio.accrue_borrow_fees(&market_data, dec!(0.001)), Decimal::ZERO);
+    }
+
+    #[test]
+    fn a_year_at_five_percent_on_ten_thousand_earns_five_hundred() {
+        let mut portfolio = Portfolio::new(dec!(10000));
+        assert_eq!(portfolio.accrue_interest(dec!(0.05), 365), dec!(500));
+        assert_eq!(portfolio.cash, dec!(10500));
+        assert_eq!(portfolio.accrue_interest(dec!(0.05), 0), Decimal::ZERO);
+
+        // A debit is charged the margin rate instead
+        let mut portfolio = margin_account();
+        portfolio.margin_interest_rate = dec!(0.08);
+        portfolio.execute_order(&market_order(dec!(150)), &MarketData::new("AAPL", dec!(100)));
+        assert_eq!(portfolio.accrue_interest(dec!(0.05), 365), dec!(-400));
+        assert_eq!(portfolio.cash, dec!(-5400));
+    }
+}
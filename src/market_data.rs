@@ -0,0 +1,713 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use flate2::read::GzDecoder;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MarketData {
+    #[serde(deserialize_with = "deserialize_symbol")]
+    pub symbol: String,
+    pub price: Decimal,
+    #[serde(default)]
+    pub available: Option<Decimal>, // Shares that can be traded at this quote, unlimited when absent
+    #[serde(default)]
+    pub bid: Option<Decimal>,
+    #[serde(default)]
+    pub ask: Option<Decimal>,
+    #[serde(default)]
+    pub depth: Option<OrderBook>, // Liquidity beyond the top of the book, from JSON quotes only
+}
+
+// One price level of an order book ladder
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BookLevel {
+    pub price: Decimal,
+    pub size: Decimal, // Shares bid or offered at the price
+}
+
+// Levels may be listed in any order; sweeps take the best price first
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct OrderBook {
+    #[serde(default)]
+    pub bids: Vec<BookLevel>,
+    #[serde(default)]
+    pub asks: Vec<BookLevel>,
+}
+
+impl OrderBook {
+    // The levels an order of quantity trades against: the asks for a buy and
+    // the bids for a sell
+    pub fn side(&self, quantity: Decimal) -> &[BookLevel] {
+        if quantity > Decimal::ZERO {
+            &self.asks
+        } else {
+            &self.bids
+        }
+    }
+
+    // Fills as much of quantity as the levels at or better than limit hold,
+    // best price first. Returns the average price and the shares filled, or
+    // None when no level is within the limit.
+    pub fn sweep(&self, quantity: Decimal, limit: Decimal) -> Option<(Decimal, Decimal)> {
+        let buy = quantity > Decimal::ZERO;
+        let mut levels: Vec<&BookLevel> = self
+            .side(quantity)
+            .iter()
+            .filter(|level| level.size > Decimal::ZERO && if buy { level.price <= limit } else { level.price >= limit })
+            .collect();
+        if buy {
+            levels.sort_by_key(|level| level.price);
+        } else {
+            levels.sort_by_key(|level| Reverse(level.price));
+        }
+
+        let mut remaining = quantity.abs();
+        let mut cost = Decimal::ZERO;
+        for level in levels {
+            let taken = level.size.min(remaining);
+            cost += taken * level.price;
+            remaining -= taken;
+            if remaining.is_zero() {
+                break;
+            }
+        }
+
+        let filled = quantity.abs() - remaining;
+        (filled > Decimal::ZERO).then(|| (cost / filled, filled))
+    }
+}
+
+impl MarketData {
+    // A plain quote: one price for both sides and unlimited liquidity
+    pub fn new(symbol: &str, price: Decimal) -> Self {
+        MarketData {
+            symbol: normalize_symbol(symbol),
+            price,
+            available: None,
+            bid: None,
+            ask: None,
+            depth: None,
+        }
+    }
+
+    // Whether every price in the quote, the bid, the ask and any order book
+    // levels included, is strictly positive. Anything else would let buys fill
+    // for nothing or less.
+    pub fn has_valid_prices(&self) -> bool {
+        let levels = self.depth.iter().flat_map(|book| book.bids.iter().chain(&book.asks));
+        [Some(self.price), self.bid, self.ask].into_iter().flatten().chain(levels.map(|level| level.price)).all(|price| price > Decimal::ZERO)
+    }
+
+    // Buys fill at the ask and sells at the bid, falling back to the single
+    // quoted price when that side isn't present
+    pub fn fill_price(&self, quantity: Decimal) -> Decimal {
+        let side = if quantity > Decimal::ZERO { self.ask } else { self.bid };
+        side.unwrap_or(self.price)
+    }
+}
+
+// Tickers are compared uppercased with surrounding whitespace removed, so
+// " aapl" and "AAPL" are the same symbol
+pub fn normalize_symbol(symbol: &str) -> String {
+    symbol.trim().to_uppercase()
+}
+
+fn deserialize_symbol<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    String::deserialize(deserializer).map(|symbol| normalize_symbol(&symbol))
+}
+
+// What the loaders do with a row or quote whose price, bid or ask is zero,
+// negative or NaN
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum InvalidPricePolicy {
+    #[default]
+    Error,
+    Skip, // Drop the row with a warning and keep loading
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    pub has_header: bool, // Skip the first line, e.g. symbol,price
+    pub invalid_prices: InvalidPricePolicy,
+    pub delimiter: char, // Field separator, e.g. '\t' or ';'
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            has_header: false,
+            invalid_prices: InvalidPricePolicy::default(),
+            delimiter: ',',
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse { line: usize, content: String }, // 1-based line number and text of a row that couldn't be read
+    InvalidPrice { line: usize, content: String }, // Row whose price, bid or ask isn't strictly positive
+    InvalidQuote { index: usize, symbol: String }, // 1-based position in a JSON list of a quote with a price that isn't strictly positive
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "{}", err),
+            LoadError::Parse { line, content } => write!(f, "line {}: could not parse '{}'", line, content),
+            LoadError::InvalidPrice { line, content } => write!(f, "line {}: prices must be positive in '{}'", line, content),
+            LoadError::InvalidQuote { index, symbol } => write!(f, "quote {} ({}): prices must be positive", index, symbol),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io(err) => Some(err),
+            LoadError::Parse { .. } | LoadError::InvalidPrice { .. } | LoadError::InvalidQuote { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+pub fn load_market_data(file_path: &str) -> Result<Vec<MarketData>, LoadError> {
+    load_market_data_with_options(file_path, &CsvOptions::default())
+}
+
+// Reads a CSV, or a gzip-compressed one when the path ends in .gz, as
+// read_market_data does
+pub fn load_market_data_with_options(file_path: &str, options: &CsvOptions) -> Result<Vec<MarketData>, LoadError> {
+    let file = File::open(file_path)?;
+    // Archives such as quotes.csv.gz are decompressed on the fly
+    if is_gzip(file_path) {
+        read_market_data(BufReader::new(GzDecoder::new(file)), options)
+    } else {
+        read_market_data(BufReader::new(file), options)
+    }
+}
+
+// Blank lines are skipped, but any other row that doesn't parse stops the read
+// with its line number rather than being read as a zero price. Rows with a zero,
+// negative or NaN price, bid or ask are handled according to
+// options.invalid_prices. Input
+// that isn't UTF-8 fails with LoadError::Io.
+pub fn read_market_data<R: BufRead>(reader: R, options: &CsvOptions) -> Result<Vec<MarketData>, LoadError> {
+    let mut data = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if index == 0 && options.has_header {
+            continue;
+        }
+
+        // A line holding only a byte-order mark counts as blank
+        let content = line.trim_start_matches('\u{feff}').trim();
+        if content.is_empty() {
+            continue;
+        }
+
+        match parse_line(content, options.delimiter) {
+            Ok(quote) => data.push(quote),
+            Err(LineError::Malformed) => return Err(LoadError::Parse { line: index + 1, content: line }),
+            Err(LineError::InvalidPrice) => match options.invalid_prices {
+                InvalidPricePolicy::Error => return Err(LoadError::InvalidPrice { line: index + 1, content: line }),
+                InvalidPricePolicy::Skip => log::warn!("skipping line {} with invalid price: {}", index + 1, line),
+            },
+        }
+    }
+
+    Ok(data)
+}
+
+fn is_gzip(file_path: &str) -> bool {
+    Path::new(file_path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("gz"))
+}
+
+pub(crate) enum LineError {
+    Malformed,
+    InvalidPrice,
+}
+
+// Reads symbol,price with optional trailing columns for the whole number of
+// shares available at the quote, then the bid and ask. Optional columns may be
+// left empty. The bid and ask must be strictly positive like the price. A
+// currency-marked price followed by a three-digit column is a formatted price
+// such as $1,234.56 that should have been quoted, and is rejected rather than
+// read as $1 with 234.56 available.
+pub(crate) fn parse_line(line: &str, delimiter: char) -> Result<MarketData, LineError> {
+    let fields = split_fields(line, delimiter)?;
+    let parts: Vec<&str> = fields.iter().map(|field| field.trim()).collect();
+    if !(2..=5).contains(&parts.len()) || parts[0].is_empty() {
+        return Err(LineError::Malformed);
+    }
+
+    let optional = |index: usize| match parts.get(index) {
+        None | Some(&"") => Ok(None),
+        Some(value) => parse_positive_price(value).map(Some),
+    };
+    let available = match parts.get(2) {
+        None | Some(&"") => None,
+        Some(value) => Some(Decimal::from(value.parse::<u64>().map_err(|_| LineError::Malformed)?)),
+    };
+    let split_price = parts[1].starts_with(['$', '€', '£', '¥'])
+        && parts.get(2).is_some_and(|group| group.len() == 3 && group.bytes().all(|byte| byte.is_ascii_digit()));
+    if split_price {
+        return Err(LineError::Malformed);
+    }
+
+    Ok(MarketData {
+        symbol: normalize_symbol(parts[0]),
+        price: parse_positive_price(parts[1])?,
+        available,
+        bid: optional(3)?,
+        ask: optional(4)?,
+        depth: None,
+    })
+}
+
+fn parse_positive_price(value: &str) -> Result<Decimal, LineError> {
+    match parse_price(value) {
+        Some(price) if price > Decimal::ZERO => Ok(price),
+        Some(_) => Err(LineError::InvalidPrice),
+        // NaN and infinity aren't decimals, but are prices rather than garbage
+        None if value.parse::<f64>().is_ok_and(|price| !price.is_finite()) => Err(LineError::InvalidPrice),
+        None => Err(LineError::Malformed),
+    }
+}
+
+// Parses a price written plainly (1234.56) or as a feed might show it, with a
+// leading currency sign and thousands separators ($1,234.56). In a
+// comma-delimited file such a price has to be quoted. Anything else is None.
+pub fn parse_price(value: &str) -> Option<Decimal> {
+    let value = value.trim();
+    let value = value.strip_prefix(['$', '€', '£', '¥']).unwrap_or(value).trim_start();
+
+    let digits = if value.contains(',') {
+        let (whole, _) = value.split_once('.').unwrap_or((value, ""));
+        let groups: Vec<&str> = whole.trim_start_matches('-').split(',').collect();
+        let grouped = !groups[0].is_empty() && groups[0].len() <= 3 && groups[1..].iter().all(|group| group.len() == 3);
+        if !grouped {
+            return None;
+        }
+        value.replace(',', "")
+    } else {
+        value.to_string()
+    };
+
+    Decimal::from_str(&digits).ok()
+}
+
+// Splits a row on the delimiter, except inside double quotes, so "BRK,B" stays
+// one field. A doubled quote inside a quoted field stands for a literal quote.
+fn split_fields(line: &str, delimiter: char) -> Result<Vec<String>, LineError> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(LineError::Malformed);
+    }
+    fields.push(field);
+
+    Ok(fields)
+}
+
+#[derive(Debug, Clone)]
+pub struct DividendEvent {
+    pub symbol: String,
+    pub per_share: Decimal,
+    pub date: NaiveDate,
+}
+
+// Loads dividends from a symbol,per_share,date CSV with ISO dates (2024-02-15),
+// skipping rows that don't parse
+pub fn load_dividends(file_path: &str) -> io::Result<Vec<DividendEvent>> {
+    let file = File::open(file_path)?;
+    let reader = BufReader::new(file);
+    let mut events = Vec::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        let parts: Vec<&str> = line.split(',').map(str::trim).collect();
+        if parts.len() == 3 {
+            let per_share = Decimal::from_str(parts[1]);
+            let date = NaiveDate::parse_from_str(parts[2], "%Y-%m-%d");
+            if let (Ok(per_share), Ok(date)) = (per_share, date) {
+                events.push(DividendEvent { symbol: normalize_symbol(parts[0]), per_share, date });
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolMeta {
+    pub name: String,
+    pub sector: String,
+}
+
+// Loads symbol,name,sector rows into a map keyed by normalized symbol,
+// skipping rows that don't have all three fields. Quote a name that contains
+// a comma.
+pub fn load_symbol_meta(file_path: &str) -> io::Result<HashMap<String, SymbolMeta>> {
+    let file = File::open(file_path)?;
+    let reader = BufReader::new(file);
+    let mut meta = HashMap::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        let Ok(fields) = split_fields(&line, ',') else {
+            continue;
+        };
+        if let [symbol, name, sector] = fields.as_slice() {
+            let (symbol, name, sector) = (symbol.trim(), name.trim(), sector.trim());
+            if !symbol.is_empty() && !sector.is_empty() {
+                let entry = SymbolMeta { name: name.to_string(), sector: sector.to_string() };
+                meta.insert(normalize_symbol(symbol), entry);
+            }
+        }
+    }
+
+    Ok(meta)
+}
+
+// Loads a JSON array of quotes such as [{"symbol": "AAPL", "price": 182.5}],
+// rejecting one with a price, bid or ask that isn't strictly positive
+pub fn load_market_data_json(file_path: &str) -> Result<Vec<MarketData>, LoadError> {
+    load_market_data_json_with_policy(file_path, InvalidPricePolicy::default())
+}
+
+pub fn load_market_data_json_with_policy(file_path: &str, invalid_prices: InvalidPricePolicy) -> Result<Vec<MarketData>, LoadError> {
+    let file = File::open(file_path)?;
+    let reader = BufReader::new(file);
+    let quotes: Vec<MarketData> = serde_json::from_reader(reader).map_err(io::Error::from)?;
+
+    check_quotes(quotes, invalid_prices)
+}
+
+// Applies the price checks the CSV loader makes to quotes read some other
+// way, such as from JSON: quotes with a price, bid or ask that isn't strictly
+// positive are an error or are dropped, as invalid_prices says
+pub fn check_quotes(quotes: Vec<MarketData>, invalid_prices: InvalidPricePolicy) -> Result<Vec<MarketData>, LoadError> {
+    let mut checked = Vec::with_capacity(quotes.len());
+
+    for (index, quote) in quotes.into_iter().enumerate() {
+        if quote.has_valid_prices() {
+            checked.push(quote);
+            continue;
+        }
+        match invalid_prices {
+            InvalidPricePolicy::Error => return Err(LoadError::InvalidQuote { index: index + 1, symbol: quote.symbol }),
+            InvalidPricePolicy::Skip => log::warn!("skipping quote {} for {} with invalid prices", index + 1, quote.symbol),
+        }
+    }
+
+    Ok(checked)
+}
+
+// Picks the JSON or CSV loader from the file extension, defaulting to CSV
+pub fn load_market_data_auto(file_path: &str) -> Result<Vec<MarketData>, LoadError> {
+    load_market_data_auto_with_options(file_path, &CsvOptions::default())
+}
+
+// load_market_data_auto with options for a CSV. A JSON file only uses
+// options.invalid_prices.
+pub fn load_market_data_auto_with_options(file_path: &str, options: &CsvOptions) -> Result<Vec<MarketData>, LoadError> {
+    let is_json = Path::new(file_path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+
+    if is_json {
+        load_market_data_json_with_policy(file_path, options.invalid_prices)
+    } else {
+        load_market_data_with_options(file_path, options)
+    }
+}
+
+// Loads and merges several files, each read as by load_market_data_auto. Every
+// symbol appears once in the result, at the position where it was first seen,
+// holding the quote from the last file (or later row) that listed it.
+pub fn load_market_data_many(file_paths: &[&str]) -> Result<Vec<MarketData>, LoadError> {
+    let mut merged: Vec<MarketData> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+
+    for file_path in file_paths {
+        for quote in load_market_data_auto(file_path)? {
+            match positions.get(&quote.symbol) {
+                Some(&index) => merged[index] = quote,
+                None => {
+                    positions.insert(quote.symbol.clone(), merged.len());
+                    merged.push(quote);
+                }
+            }
+        }
+    }
+
+    Ok(merged)
+}
+
+// Quotes indexed by symbol, built once so repeated lookups don't rescan the data.
+// When a symbol appears more than once the first quote is kept, matching
+// find_market_price.
+#[derive(Debug, Default)]
+pub struct MarketSnapshot {
+    quotes: HashMap<String, MarketData>,
+}
+
+impl MarketSnapshot {
+    pub fn new(market_data: &[MarketData]) -> Self {
+        let mut quotes = HashMap::with_capacity(market_data.len());
+        for data in market_data {
+            quotes.entry(normalize_symbol(&data.symbol)).or_insert_with(|| data.clone());
+        }
+
+        MarketSnapshot { quotes }
+    }
+
+    pub fn quote(&self, symbol: &str) -> Option<&MarketData> {
+        self.quotes.get(&normalize_symbol(symbol))
+    }
+
+    pub fn price(&self, symbol: &str) -> Option<Decimal> {
+        self.quote(symbol).map(|data| data.price)
+    }
+
+    // Replaces the symbol's quote, as a live feed moves on
+    pub fn update(&mut self, data: MarketData) {
+        self.quotes.insert(normalize_symbol(&data.symbol), data);
+    }
+
+    // Every symbol's quote, sorted by symbol so whatever acts on them in turn
+    // does so in the same order every run
+    pub fn quotes(&self) -> Vec<MarketData> {
+        let mut quotes: Vec<MarketData> = self.quotes.values().cloned().collect();
+        quotes.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        quotes
+    }
+}
+
+// Linear scan kept for one-off lookups; build a MarketSnapshot for repeated ones
+pub fn find_market_price(market_data: &[MarketData], symbol: &str) -> Option<Decimal> {
+    let symbol = normalize_symbol(symbol);
+    market_data.iter().find(|&data| data.symbol == symbol).map(|data| data.price)
+}
+
+// Like find_market_price, but returns the side an order of the given quantity fills on
+pub fn find_fill_price(market_data: &[MarketData], symbol: &str, quantity: Decimal) -> Option<Decimal> {
+    let symbol = normalize_symbol(symbol);
+    market_data.iter().find(|&data| data.symbol == symbol).map(|data| data.fill_price(quantity))
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn header_is_skipped_and_a_malformed_line_is_reported() {
+        let options = CsvOptions { has_header: true, ..CsvOptions::default() };
+        let quotes = read_market_data("symbol,price\nAAPL,150.05\n\nMSFT,280.30\n".as_bytes(), &options).unwrap();
+        assert_eq!(quotes.len(), 2);
+        assert_eq!(quotes[1].price, Decimal::from_str("280.30").unwrap());
+
+        let err = read_market_data("symbol,price\nAAPL,150.05\nMSFT,abc\n".as_bytes(), &options).unwrap_err();
+        assert!(matches!(err, LoadError::Parse { line: 3, ref content } if content == "MSFT,abc"));
+
+        // Without the option the header is a malformed first row, not a free share
+        let err = read_market_data("symbol,price\nAAPL,150.05\n".as_bytes(), &CsvOptions::default()).unwrap_err();
+        assert!(matches!(err, LoadError::Parse { line: 1, .. }));
+    }
+
+    #[test]
+    fn negative_zero_and_nan_prices_are_rejected_or_skipped() {
+        for row in ["AAPL,-5", "AAPL,0", "AAPL,NaN", "AAPL,100,,-5,-5", "AAPL,100,,99,0", "AAPL,100,,nan,101"] {
+            let err = read_market_data(row.as_bytes(), &CsvOptions::default()).unwrap_err();
+            assert!(matches!(err, LoadError::InvalidPrice { line: 1, .. }), "{}", row);
+        }
+
+        let options = CsvOptions { invalid_prices: InvalidPricePolicy::Skip, ..CsvOptions::default() };
+        let quotes = read_market_data("AAPL,-5\nMSFT,0\nGOOG,NaN\nAMZN,100,,-5,-5\nTSLA,200,,199.5,200.5\n".as_bytes(), &options).unwrap();
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].symbol, "TSLA");
+    }
+
+    #[test]
+    fn json_quotes_get_the_same_price_checks() {
+        let path = std::env::temp_dir().join(format!("quotes_{}.json", std::process::id()));
+        let json = r#"[{"symbol": "aapl", "price": 100, "bid": 99, "ask": 0}, {"symbol": "MSFT", "price": 300}]"#;
+        std::fs::write(&path, json).unwrap();
+        let path_str = path.to_str().unwrap();
+
+        let rejected = load_market_data_auto(path_str);
+        let skipped = load_market_data_json_with_policy(path_str, InvalidPricePolicy::Skip);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(rejected, Err(LoadError::InvalidQuote { index: 1, ref symbol }) if symbol == "AAPL"));
+        let skipped = skipped.unwrap();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].symbol, "MSFT");
+    }
+
+    #[test]
+    fn symbols_are_trimmed_and_upper_cased() {
+        assert_eq!(normalize_symbol("  aApL\t"), "AAPL");
+        assert_eq!(normalize_symbol("brk.b "), "BRK.B");
+
+        let quotes = read_market_data(" msft ,280.30\n".as_bytes(), &CsvOptions::default()).unwrap();
+        assert_eq!(quotes[0].symbol, "MSFT");
+        assert_eq!(find_market_price(&quotes, "Msft "), Some(quotes[0].price));
+    }
+
+    #[test]
+    fn a_symbol_in_several_files_takes_the_last_files_quote() {
+        let dir = std::env::temp_dir();
+        let first = dir.join(format!("quotes_first_{}.csv", std::process::id()));
+        let second = dir.join(format!("quotes_second_{}.csv", std::process::id()));
+        std::fs::write(&first, "AAPL,150\nMSFT,280\n").unwrap();
+        std::fs::write(&second, "GOOG,120\naapl,155\n").unwrap();
+
+        let merged = load_market_data_many(&[first.to_str().unwrap(), second.to_str().unwrap()]);
+        std::fs::remove_file(&first).unwrap();
+        std::fs::remove_file(&second).unwrap();
+
+        let merged = merged.unwrap();
+        let symbols: Vec<&str> = merged.iter().map(|quote| quote.symbol.as_str()).collect();
+        assert_eq!(symbols, ["AAPL", "MSFT", "GOOG"]);
+        assert_eq!(merged[0].price, Decimal::from(155));
+    }
+
+    #[test]
+    fn fields_split_on_the_configured_delimiter_outside_quotes() {
+        let options = CsvOptions { delimiter: '\t', ..CsvOptions::default() };
+        let quotes = read_market_data("AAPL\t150.05\t\t150\t150.10\n".as_bytes(), &options).unwrap();
+        assert_eq!(quotes[0].price, Decimal::from_str("150.05").unwrap());
+        assert_eq!(quotes[0].available, None);
+        assert_eq!(quotes[0].ask, Some(Decimal::from_str("150.10").unwrap()));
+
+        // A comma inside quotes belongs to the field, not the row
+        let quotes = read_market_data("\"BRK,A\",\"$1,234.56\"\n".as_bytes(), &CsvOptions::default()).unwrap();
+        assert_eq!(quotes[0].symbol, "BRK,A");
+        assert_eq!(quotes[0].price, Decimal::from_str("1234.56").unwrap());
+
+        // Unquoted, the separator would split the price into a $1 quote with
+        // shares available, so the row is refused instead
+        for row in ["AAPL,$1,234.56", "AAPL,$1,234", "AAPL,100,2.5", "AAPL,100,-3"] {
+            let err = read_market_data(row.as_bytes(), &CsvOptions::default()).unwrap_err();
+            assert!(matches!(err, LoadError::Parse { line: 1, .. }), "{}", row);
+        }
+        let quotes = read_market_data("AAPL,1,234\nMSFT,$300,1000\n".as_bytes(), &CsvOptions::default()).unwrap();
+        assert_eq!(quotes[0].available, Some(Decimal::from(234)));
+        assert_eq!(quotes[1].available, Some(Decimal::from(1000)));
+    }
+
+    #[test]
+    fn a_gzipped_csv_loads_like_the_plain_one() {
+        use std::io::Write;
+
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+
+        let csv = "AAPL,150.05,100,150,150.10\nMSFT,280.30\n";
+        let path = std::env::temp_dir().join(format!("quotes_{}.csv.gz", std::process::id()));
+        let mut encoder = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(csv.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        let loaded = load_market_data_auto(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        let fields = |quotes: Vec<MarketData>| -> Vec<_> {
+            quotes.into_iter().map(|quote| (quote.symbol, quote.price, quote.available, quote.bid, quote.ask)).collect()
+        };
+        let plain = read_market_data(csv.as_bytes(), &CsvOptions::default()).unwrap();
+        assert_eq!(fields(loaded.unwrap()), fields(plain));
+    }
+
+    #[test]
+    fn prices_parse_with_or_without_a_currency_sign_and_separators() {
+        let price = Some(Decimal::from_str("1234.56").unwrap());
+        assert_eq!(parse_price("$1,234.56"), price);
+        assert_eq!(parse_price("1234.56"), price);
+        assert_eq!(parse_price(" € 1,234.56 "), price);
+        assert_eq!(parse_price("abc"), None);
+        // Separators only count in groups of three
+        assert_eq!(parse_price("12,34.56"), None);
+    }
+
+    #[test]
+    fn snapshot_quotes_come_back_in_symbol_order() {
+        let mut snapshot = MarketSnapshot::default();
+        for symbol in ["TSLA", "aapl", "MSFT", "GOOG", "AMZN", "NVDA"] {
+            snapshot.update(MarketData::new(symbol, Decimal::ONE));
+        }
+
+        let symbols: Vec<String> = snapshot.quotes().into_iter().map(|quote| quote.symbol).collect();
+        assert_eq!(symbols, ["AAPL", "AMZN", "GOOG", "MSFT", "NVDA", "TSLA"]);
+    }
+
+    proptest! {
+        // Ragged, truncated or binary input may be rejected, but must never
+        // panic or come back as a quote with a price, bid or ask that isn't
+        // strictly positive
+        #[test]
+        fn arbitrary_bytes_never_panic_or_yield_bad_prices(bytes in prop::collection::vec(any::<u8>(), 0..512)) {
+            if let Ok(quotes) = read_market_data(bytes.as_slice(), &CsvOptions::default()) {
+                prop_assert!(quotes.iter().all(MarketData::has_valid_prices));
+            }
+        }
+
+        #[test]
+        fn csv_like_rows_never_panic_or_yield_bad_prices(text in "([A-Za-z \"]{0,6}[,;\t]{0,2}[-$0-9,.eEnaNif]{0,10}[,\n]){0,10}") {
+            let options = CsvOptions { invalid_prices: InvalidPricePolicy::Skip, ..CsvOptions::default() };
+            if let Ok(quotes) = read_market_data(text.as_bytes(), &options) {
+                prop_assert!(quotes.iter().all(MarketData::has_valid_prices));
+            }
+        }
+
+        #[test]
+        fn rows_with_a_bid_and_ask_never_yield_bad_prices(text in "(AAPL,[-0-9.]{1,4},[0-9]{0,2},[-0-9.aNinf]{0,4},[-0-9.aNinf]{0,4}\n){0,10}") {
+            for invalid_prices in [InvalidPricePolicy::Error, InvalidPricePolicy::Skip] {
+                let options = CsvOptions { invalid_prices, ..CsvOptions::default() };
+                if let Ok(quotes) = read_market_data(text.as_bytes(), &options) {
+                    prop_assert!(quotes.iter().all(MarketData::has_valid_prices));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::{BTreeMap, HashMap};
+
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+
+use crate::market_data::MarketData;
+use crate::portfolio::Portfolio;
+
+// Fewest period returns value_at_risk will estimate a percentile from
+pub const MIN_VAR_RETURNS: usize = 5;
+
+impl Portfolio {
+    // Gain as a fraction of the starting capital, e.g. 0.1 for 10%. None when
+    // the portfolio started with no cash, as there is nothing to divide by.
+    pub fn percent_return(&self, current_market_data: &[MarketData]) -> Option<f32> {
+        if self.initial_cash().is_zero() {
+            return None;
+        }
+
+        let gain = self.market_value(current_market_data) - self.initial_cash();
+        (gain / self.initial_cash()).to_f32()
+    }
+
+    // Compounds the percent return over a 365-day year. None when no time has
+    // passed, or when the account has lost everything so there's no growth rate.
+    pub fn annualized_return(&self, current_market_data: &[MarketData], days_held: u32) -> Option<f32> {
+        if days_held == 0 {
+            return None;
+        }
+
+        let growth = 1.0 + self.percent_return(current_market_data)?;
+        if growth <= 0.0 {
+            return None;
+        }
+
+        Some(growth.powf(365.0 / days_held as f32) - 1.0)
+    }
+
+    // Growth of the equity curve from its first point to its last, compounded
+    // to a year of the given number of periods, e.g. 252 for daily points.
+    // None with fewer than two points or a first point that isn't positive.
+    pub fn annualized_curve_return(&self, periods_per_year: f32) -> Option<f32> {
+        let curve = self.equity_curve();
+        let (&(_, first), &(_, last)) = (curve.first()?, curve.last()?);
+        if curve.len() < 2 || first <= Decimal::ZERO {
+            return None;
+        }
+
+        let growth = (last / first).to_f32()?;
+        let periods = (curve.len() - 1) as f32;
+        Some(growth.powf(periods_per_year / periods) - 1.0)
+    }
+
+    // Annualized curve return over the max drawdown. A curve that never falls
+    // has no drawdown to divide by, so the ratio is f32::INFINITY when it grew
+    // and zero otherwise, as it is when there's no return to measure.
+    pub fn calmar_ratio(&self, periods_per_year: f32) -> f32 {
+        let Some(annualized) = self.annualized_curve_return(periods_per_year) else {
+            return 0.0;
+        };
+
+        let drawdown = self.max_drawdown();
+        if drawdown == 0.0 {
+            return if annualized > 0.0 { f32::INFINITY } else { 0.0 };
+        }
+
+        annualized / drawdown
+    }
+
+    // Largest decline from a running peak of the equity curve to a later
+    // trough, as a fraction of that peak. Zero for a curve with fewer than two
+    // points or one that never falls.
+    pub fn max_drawdown(&self) -> f32 {
+        let mut peak = Decimal::ZERO;
+        let mut max_drawdown = Decimal::ZERO;
+
+        for &(_, value) in self.equity_curve() {
+            peak = peak.max(value);
+            if peak > Decimal::ZERO {
+                max_drawdown = max_drawdown.max((peak - value) / peak);
+            }
+        }
+
+        max_drawdown.to_f32().unwrap_or(0.0)
+    }
+
+    // Fractional change between each pair of consecutive equity points.
+    // Periods starting at a value of zero or below have no defined return and
+    // are skipped.
+    pub fn period_returns(&self) -> Vec<f32> {
+        self.equity_curve()
+            .windows(2)
+            .filter(|pair| pair[0].1 > Decimal::ZERO)
+            .filter_map(|pair| ((pair[1].1 - pair[0].1) / pair[0].1).to_f32())
+            .collect()
+    }
+
+    // Mean period return in excess of the per-period risk-free rate, divided by
+    // the standard deviation of those excess returns. Zero when there are too
+    // few equity points or the returns don't vary.
+    pub fn sharpe_ratio(&self, risk_free_rate: f32) -> f32 {
+        let excess: Vec<f32> = self.period_returns().iter().map(|r| r - risk_free_rate).collect();
+
+        let deviation = std_dev(&excess);
+        if deviation == 0.0 {
+            return 0.0;
+        }
+
+        mean(&excess) / deviation
+    }
+
+    // Mean period return in excess of the target, divided by the downside
+    // deviation: the root mean square of the shortfalls below the target, with
+    // periods at or above it counting as zero. With no shortfalls at all the
+    // ratio is unbounded, so it is f32::INFINITY when the mean excess is
+    // positive and zero otherwise, as it is with no returns.
+    pub fn sortino_ratio(&self, target_return: f32) -> f32 {
+        let excess: Vec<f32> = self.period_returns().iter().map(|r| r - target_return).collect();
+        if excess.is_empty() {
+            return 0.0;
+        }
+
+        let downside = (excess.iter().map(|r| r.min(0.0).powi(2)).sum::<f32>() / excess.len() as f32).sqrt();
+        if downside == 0.0 {
+            return if mean(&excess) > 0.0 { f32::INFINITY } else { 0.0 };
+        }
+
+        mean(&excess) / downside
+    }
+
+    // Covariance of the period returns with the benchmark's over the
+    // benchmark's variance. The two series are assumed to start together and
+    // are cut to the shorter of them. Zero with fewer than two common periods or
+    // a benchmark that doesn't vary.
+    pub fn beta(&self, benchmark_returns: &[f32]) -> f32 {
+        let returns = self.period_returns();
+        let len = returns.len().min(benchmark_returns.len());
+        if len < 2 {
+            return 0.0;
+        }
+
+        let (returns, benchmark) = (&returns[..len], &benchmark_returns[..len]);
+        let (mean_return, mean_benchmark) = (mean(returns), mean(benchmark));
+
+        let covariance: f32 = returns.iter().zip(benchmark).map(|(r, b)| (r - mean_return) * (b - mean_benchmark)).sum();
+        let variance: f32 = benchmark.iter().map(|b| (b - mean_benchmark).powi(2)).sum();
+        if variance == 0.0 {
+            return 0.0;
+        }
+
+        covariance / variance
+    }
+
+    // Historical value at risk: the loss over one period, in currency, that the
+    // period returns stayed within at the given confidence, e.g. 0.95. The
+    // return at that percentile (by nearest rank, so the 5th worst of 100 for
+    // 95%) is applied to the latest equity. None for a confidence outside 0..1
+    // or with fewer than MIN_VAR_RETURNS returns to draw from.
+    pub fn value_at_risk(&self, confidence: f32) -> Option<f32> {
+        if !(confidence > 0.0 && confidence < 1.0) {
+            return None;
+        }
+
+        let mut returns = self.period_returns();
+        if returns.len() < MIN_VAR_RETURNS {
+            return None;
+        }
+        returns.sort_by(f32::total_cmp);
+
+        let rank = ((1.0 - confidence) * returns.len() as f32).ceil().max(1.0) as usize;
+        let current = self.equity_curve().last()?.1.to_f32()?;
+        Some((-returns[rank - 1]).max(0.0) * current)
+    }
+
+    // Standard deviation of the period returns annualized by the number of
+    // periods in a year, e.g. 252 for daily points. Zero until the curve has at
+    // least two period returns to compare.
+    pub fn volatility(&self, periods_per_year: f32) -> f32 {
+        std_dev(&self.period_returns()) * periods_per_year.sqrt()
+    }
+}
+
+// Pearson correlation of every pair of return series, keyed both ways round
+// and including each symbol with itself. As with beta the series are assumed
+// to start together and are cut to the shorter of each pair; a pair with fewer
+// than two common periods, or where either series doesn't vary, is 0.0.
+pub fn correlation_matrix(returns: &HashMap<String, Vec<f32>>) -> BTreeMap<(String, String), f32> {
+    let mut matrix = BTreeMap::new();
+    for (a, a_returns) in returns {
+        for (b, b_returns) in returns {
+            matrix.insert((a.clone(), b.clone()), correlation(a_returns, b_returns));
+        }
+    }
+    matrix
+}
+
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let len = a.len().min(b.len());
+    if len < 2 {
+        return 0.0;
+    }
+
+    let (a, b) = (&a[..len], &b[..len]);
+    let (mean_a, mean_b) = (mean(a), mean(b));
+
+    let covariance: f32 = a.iter().zip(b).map(|(x, y)| (x - mean_a) * (y - mean_b)).sum();
+    let variance_a: f32 = a.iter().map(|x| (x - mean_a).powi(2)).sum();
+    let variance_b: f32 = b.iter().map(|y| (y - mean_b).powi(2)).sum();
+    if variance_a == 0.0 || variance_b == 0.0 {
+        return 0.0;
+    }
+
+    covariance / (variance_a * variance_b).sqrt()
+}
+
+pub(crate) fn mean(values: &[f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+
+    values.iter().sum::<f32>() / values.len() as f32
+}
+
+// Sample standard deviation, zero for fewer than two values
+pub(crate) fn std_dev(values: &[f32]) -> f32 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+
+    let mean = mean(values);
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / (values.len() - 1) as f32;
+    variance.sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::order::{Order, OrderType, TimeInForce};
+
+    // All cash in 10 shares at 100, with the equity recorded at each later price
+    fn recorded(prices: &[Decimal]) -> Portfolio {
+        let mut portfolio = Portfolio::new(dec!(1000));
+        portfolio.record_equity = true;
+        let buy = Order {
+            symbol: "AAPL".to_string(),
+            quantity: dec!(10),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        };
+        portfolio.execute_order(&buy, &MarketData::new("AAPL", dec!(100)));
+
+        for (index, &price) in prices.iter().enumerate() {
+            portfolio.apply_snapshot(index as i64, &[MarketData::new("AAPL", price)]);
+        }
+        portfolio
+    }
+
+    #[test]
+    fn percent_return_of_a_ten_percent_gain() {
+        let portfolio = recorded(&[]);
+        let market_data = [MarketData::new("AAPL", dec!(110))];
+
+        assert!((portfolio.percent_return(&market_data).unwrap() - 0.1).abs() < 1e-6);
+        assert!((portfolio.annualized_return(&market_data, 365).unwrap() - 0.1).abs() < 1e-6);
+        assert_eq!(portfolio.annualized_return(&market_data, 0), None);
+        assert_eq!(Portfolio::new(Decimal::ZERO).percent_return(&market_data), None);
+    }
+
+    #[test]
+    fn max_drawdown_finds_the_deepest_trough_after_a_recovery() {
+        // A 10% dip from 1100, a new high of 1200, then a 25% fall to 900
+        let portfolio = recorded(&[dec!(100), dec!(110), dec!(99), dec!(120), dec!(90), dec!(100)]);
+
+        assert!((portfolio.max_drawdown() - 0.25).abs() < 1e-6);
+        assert_eq!(recorded(&[]).max_drawdown(), 0.0);
+        assert_eq!(recorded(&[dec!(100)]).max_drawdown(), 0.0);
+        assert_eq!(recorded(&[dec!(100), dec!(105), dec!(120)]).max_drawdown(), 0.0);
+    }
+
+    #[test]
+    fn calmar_ratio_divides_annualized_return_by_drawdown() {
+        // Equity goes 1000, 1100, 990, 1210: 21% over three periods, taken as a
+        // year, with a 10% drawdown from 1100 to 990
+        let portfolio = recorded(&[dec!(100), dec!(110), dec!(99), dec!(121)]);
+
+        assert!((portfolio.calmar_ratio(3.0) - 2.1).abs() < 1e-4);
+    }
+
+    #[test]
+    fn calmar_ratio_is_infinite_without_drawdown() {
+        let portfolio = recorded(&[dec!(100), dec!(110), dec!(121)]);
+
+        assert_eq!(portfolio.calmar_ratio(252.0), f32::INFINITY);
+        assert_eq!(recorded(&[dec!(100)]).calmar_ratio(252.0), 0.0);
+    }
+
+    #[test]
+    fn correlation_matrix_pairs_every_symbol() {
+        let returns = HashMap::from([
+            ("AAPL".to_string(), vec![0.01, -0.02, 0.03, 0.0]),
+            ("MSFT".to_string(), vec![0.02, -0.04, 0.06, 0.0]),
+            ("GLD".to_string(), vec![-0.01, 0.02, -0.03, 0.0]),
+            ("CASH".to_string(), vec![0.0, 0.0, 0.0, 0.0]),
+        ]);
+        let matrix = correlation_matrix(&returns);
+        let pair = |a: &str, b: &str| matrix[&(a.to_string(), b.to_string())];
+
+        assert_eq!(matrix.len(), 16);
+        assert!((pair("AAPL", "MSFT") - 1.0).abs() < 1e-5);
+        assert!((pair("AAPL", "GLD") + 1.0).abs() < 1e-5);
+        assert_eq!(pair("GLD", "AAPL"), pair("AAPL", "GLD"));
+        assert_eq!(pair("AAPL", "CASH"), 0.0);
+    }
+
+    #[test]
+    fn beta_is_one_against_identical_returns() {
+        let portfolio = recorded(&[dec!(100), dec!(110), dec!(99), dec!(108.9)]);
+
+        let benchmark = portfolio.period_returns();
+        assert!((portfolio.beta(&benchmark) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn beta_is_zero_against_an_uncorrelated_benchmark() {
+        // Returns of +10%, -10%, +10%, -10% against a benchmark whose moves
+        // follow a pattern with zero covariance
+        let portfolio = recorded(&[dec!(100), dec!(110), dec!(99), dec!(108.9), dec!(98.01)]);
+
+        assert!(portfolio.beta(&[0.01, 0.01, -0.01, -0.01]).abs() < 1e-5);
+        assert_eq!(portfolio.beta(&[0.02, 0.02, 0.02, 0.02]), 0.0);
+    }
+
+    #[test]
+    fn value_at_risk_takes_the_nearest_rank_loss() {
+        // Returns of +10%, -10%, +5%, -20%, +25% and -4%, ending at 1000
+        let portfolio = recorded(&[dec!(100), dec!(110), dec!(99), dec!(103.95), dec!(83.16), dec!(103.95), dec!(99.792)]);
+        let current = 997.92;
+
+        // 80% of 6 returns leaves rank 2 from the bottom, the -10%
+        assert!((portfolio.value_at_risk(0.8).unwrap() - 0.1 * current).abs() < 1e-2);
+        // 95% falls on the worst return, the -20%
+        assert!((portfolio.value_at_risk(0.95).unwrap() - 0.2 * current).abs() < 1e-2);
+    }
+
+    #[test]
+    fn value_at_risk_needs_enough_returns() {
+        let portfolio = recorded(&[dec!(100), dec!(110), dec!(99), dec!(103.95)]);
+
+        assert_eq!(portfolio.value_at_risk(0.95), None);
+    }
+
+    #[test]
+    fn volatility_annualizes_the_sample_deviation_of_returns() {
+        // Equity goes 1000, 1100, 990: returns of +10% and -10%
+        let portfolio = recorded(&[dec!(100), dec!(110), dec!(99)]);
+
+        let expected = 0.02f32.sqrt() * 252f32.sqrt();
+        assert!((portfolio.volatility(252.0) - expected).abs() < 1e-5);
+    }
+
+    #[test]
+    fn sortino_ratio_divides_by_downside_deviation_only() {
+        // Returns of +10%, -10% and +10%: mean excess 1/30, downside sqrt(0.01 / 3)
+        let portfolio = recorded(&[dec!(100), dec!(110), dec!(99), dec!(108.9)]);
+
+        let expected = (0.1f32 / 3.0) / (0.01f32 / 3.0).sqrt();
+        assert!((portfolio.sortino_ratio(0.0) - expected).abs() < 1e-4);
+    }
+
+    #[test]
+    fn sortino_ratio_is_infinite_without_downside() {
+        let portfolio = recorded(&[dec!(100), dec!(110), dec!(121)]);
+
+        assert_eq!(portfolio.sortino_ratio(0.0), f32::INFINITY);
+    }
+
+    #[test]
+    fn volatility_is_zero_with_fewer_than_two_points() {
+        assert_eq!(recorded(&[]).volatility(252.0), 0.0);
+        assert_eq!(recorded(&[dec!(100)]).volatility(252.0), 0.0);
+    }
+}
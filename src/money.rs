@@ -0,0 +1,28 @@
+use rust_decimal::{Decimal, RoundingStrategy};
+
+// How cash amounts are rounded wherever they are stored or shown: to the cent,
+// with halves going to the even cent so repeated rounding doesn't drift one way
+pub const MONEY_DECIMAL_PLACES: u32 = 2;
+pub const MONEY_ROUNDING: RoundingStrategy = RoundingStrategy::MidpointNearestEven;
+
+// Applied to every change to cash and to money before it is displayed or
+// exported. Formatting a Decimal with {:.2} truncates rather than rounds, so
+// amounts go through here first for what's shown to match what's stored.
+pub fn round_money(amount: Decimal) -> Decimal {
+    amount.round_dp_with_strategy(MONEY_DECIMAL_PLACES, MONEY_ROUNDING)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn halves_round_to_the_even_cent() {
+        assert_eq!(round_money(dec!(1.005)), dec!(1.00));
+        assert_eq!(round_money(dec!(1.015)), dec!(1.02));
+        assert_eq!(round_money(dec!(-1.015)), dec!(-1.02));
+        assert_eq!(round_money(dec!(1.0051)), dec!(1.01));
+    }
+}
@@ -0,0 +1,93 @@
+use rayon::prelude::*;
+
+use crate::backtest::backtest;
+use crate::metrics::{mean, std_dev};
+use crate::portfolio::Portfolio;
+use crate::strategy::Strategy;
+use crate::synthetic::generate_random_walk;
+
+// Distribution of final returns over a set of simulated price paths
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonteCarloResult {
+    pub returns: Vec<f32>, // Final percent return of each path, in path order
+    pub mean: f32,
+    pub median: f32,
+    pub std_dev: f32,
+    pub percentile_5: f32,
+    pub percentile_95: f32,
+}
+
+impl MonteCarloResult {
+    pub fn from_returns(returns: Vec<f32>) -> Self {
+        let mut sorted = returns.clone();
+        sorted.sort_by(f32::total_cmp);
+
+        MonteCarloResult {
+            mean: mean(&returns),
+            median: percentile(&sorted, 0.5),
+            std_dev: std_dev(&returns),
+            percentile_5: percentile(&sorted, 0.05),
+            percentile_95: percentile(&sorted, 0.95),
+            returns,
+        }
+    }
+}
+
+// Backtests a fresh copy of the strategy over each of paths random walks of
+// one symbol, starting from a default portfolio every time. Path i uses a seed
+// derived from the master seed and i, so results don't depend on how the paths
+// are spread across threads.
+pub fn monte_carlo<S>(strategy: &S, paths: usize, steps: usize, symbol: &str, start_price: f32, volatility: f32, seed: u64) -> MonteCarloResult
+where
+    S: Strategy + Clone + Send + Sync,
+{
+    let returns = (0..paths)
+        .into_par_iter()
+        .map(|path| {
+            let walk = generate_random_walk(symbol, start_price, steps, volatility, path_seed(seed, path));
+            let last = walk.last().cloned().into_iter().collect::<Vec<_>>();
+            let snapshots = walk.into_iter().map(|quote| vec![quote]).collect();
+
+            let portfolio = backtest(Portfolio::default(), snapshots, &mut strategy.clone());
+            portfolio.percent_return(&last).unwrap_or(0.0)
+        })
+        .collect();
+
+    MonteCarloResult::from_returns(returns)
+}
+
+fn path_seed(seed: u64, path: usize) -> u64 {
+    seed ^ (path as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+// Linearly interpolated percentile of already sorted values, zero when empty
+fn percentile(sorted: &[f32], fraction: f32) -> f32 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+
+    let rank = fraction * (sorted.len() - 1) as f32;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::strategy::BuyAndHold;
+
+    #[test]
+    fn the_same_master_seed_gives_identical_aggregates() {
+        let strategy = BuyAndHold::new("AAPL", dec!(5000));
+        let run = |seed| monte_carlo(&strategy, 64, 50, "AAPL", 100.0, 0.02, seed);
+
+        let first = run(7);
+        assert_eq!(first, run(7));
+        assert_eq!(first.returns.len(), 64);
+        assert_ne!(first.returns, run(8).returns);
+        assert!(first.percentile_5 <= first.median && first.median <= first.percentile_95);
+    }
+}
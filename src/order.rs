@@ -0,0 +1,251 @@
+use std::error::Error;
+use std::fmt;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::market_data::{find_market_price, normalize_symbol, MarketData};
+use crate::money::round_money;
+use crate::portfolio::Portfolio;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct OrderId(pub u64);
+
+impl fmt::Display for OrderId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+// Links resting orders so that the first of them to fill cancels the rest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct OrderGroup(pub u64);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum OrderType {
+    Market,
+    Limit(Decimal),
+    Stop(Decimal),
+    TakeProfit(Decimal), // Target price that closes the position at market once reached
+    StopLimit { stop: Decimal, limit: Decimal },
+    TrailingStop { offset: Decimal },
+}
+
+// How long an order may wait for its price before it is dropped
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum TimeInForce {
+    Day, // Rests until the end of the trading day
+    #[default]
+    Gtc, // Good till cancelled: rests until it fills or is cancelled
+    Ioc, // Immediate or cancel: fills what it can at once and never rests
+    Fok, // Fill or kill: fills completely at once or is rejected
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Order {
+    pub symbol: String,
+    pub quantity: Decimal, // Positive to buy, negative to sell; may be fractional
+    pub order_type: OrderType,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+}
+
+impl Order {
+    // Sizes an order by value: the most whole shares at the market price that
+    // don't exceed the dollar amount, buying for a positive amount and selling
+    // for a negative one. Without a positive price the quantity is zero, which
+    // validate rejects.
+    pub fn for_dollars(symbol: &str, dollars: Decimal, market_price: Decimal, order_type: OrderType) -> Order {
+        let quantity = if market_price > Decimal::ZERO {
+            (dollars / market_price).trunc()
+        } else {
+            Decimal::ZERO
+        };
+
+        Order {
+            symbol: symbol.to_string(),
+            quantity,
+            order_type,
+            time_in_force: TimeInForce::Gtc,
+        }
+    }
+
+    // Sizes an order as a fraction of the portfolio, e.g. 0.1 for 10%. A buy,
+    // for a positive fraction, spends that share of total equity at current
+    // prices, converted to whole shares at the symbol's quote as in
+    // for_dollars. A sell, for a negative fraction, is taken against the
+    // position instead: -0.1 sells a tenth of the shares held, not a tenth of
+    // equity's worth. Sells are whole shares too, capped at the long position.
+    // A buy without a quote for the symbol has zero quantity, which validate
+    // rejects, as does a sell of a symbol not held long.
+    pub fn for_portfolio_pct(symbol: &str, pct: Decimal, portfolio: &Portfolio, market_data: &[MarketData], order_type: OrderType) -> Order {
+        if pct >= Decimal::ZERO {
+            let price = find_market_price(market_data, symbol).unwrap_or(Decimal::ZERO);
+            return Order::for_dollars(symbol, pct * portfolio.market_value(market_data), price, order_type);
+        }
+
+        let held = portfolio.holdings.get(&normalize_symbol(symbol)).map_or(Decimal::ZERO, |position| position.quantity);
+        Order {
+            symbol: symbol.to_string(),
+            quantity: -(held.max(Decimal::ZERO) * pct.abs().min(Decimal::ONE)).trunc(),
+            order_type,
+            time_in_force: TimeInForce::Gtc,
+        }
+    }
+
+    // Rejects orders that can't mean anything: no quantity, no symbol, a limit,
+    // stop or take-profit price that isn't positive, or a negative trailing offset
+    pub fn validate(&self) -> Result<(), OrderError> {
+        if self.quantity.is_zero() {
+            return Err(OrderError::ZeroQuantity);
+        }
+        if self.symbol.trim().is_empty() {
+            return Err(OrderError::EmptySymbol);
+        }
+
+        match self.order_type {
+            OrderType::Limit(price) | OrderType::Stop(price) | OrderType::TakeProfit(price) if price <= Decimal::ZERO => Err(OrderError::InvalidPrice(price)),
+            OrderType::StopLimit { stop, .. } if stop <= Decimal::ZERO => Err(OrderError::InvalidPrice(stop)),
+            OrderType::StopLimit { limit, .. } if limit <= Decimal::ZERO => Err(OrderError::InvalidPrice(limit)),
+            OrderType::TrailingStop { offset } if offset < Decimal::ZERO => Err(OrderError::InvalidPrice(offset)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderError {
+    InsufficientCash { required: Decimal, available: Decimal },
+    InsufficientShares { requested: Decimal, held: Decimal },
+    LimitNotMet,
+    StopNotTriggered,
+    TargetNotReached,
+    UnknownOrder(OrderId),
+    NotLimitOrder(OrderId),
+    AlreadyGrouped(OrderId),
+    GroupTooSmall, // A group needs at least two orders
+    InsufficientLiquidity { requested: Decimal, available: Decimal },
+    TradingHalted, // The symbol's circuit breaker tripped this tick
+    MarketClosed, // Submitted outside the trading session
+    RateLimited, // Too soon after the last order for the same symbol
+    InvalidWeights(Decimal), // Rebalance targets that are negative or add up to more than one
+    PositionLimitExceeded { value: Decimal, limit: Decimal }, // Position value the buy would reach, and the most allowed
+    InvalidLotSize { quantity: Decimal, lot_size: Decimal }, // Quantity that isn't a whole number of lots
+    NoLiquidity,
+    ZeroQuantity,
+    EmptySymbol,
+    InvalidPrice(Decimal),
+}
+
+// Outcome of submitting an order, or of re-evaluating a resting one
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderStatus {
+    Filled,
+    PartiallyFilled(Decimal), // Quantity that was filled, signed like Order::quantity
+    Resting,
+    Cancelled, // Removed from the book because another order in its group filled
+    Expired, // A day order still resting at the end of the day
+    Rejected(OrderError),
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderStatus::Filled => write!(f, "filled"),
+            OrderStatus::PartiallyFilled(quantity) => write!(f, "partially filled ({})", quantity),
+            OrderStatus::Resting => write!(f, "resting"),
+            OrderStatus::Cancelled => write!(f, "cancelled"),
+            OrderStatus::Expired => write!(f, "expired"),
+            OrderStatus::Rejected(err) => write!(f, "rejected: {}", err),
+        }
+    }
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderError::InsufficientCash { required, available } => write!(
+                f,
+                "not enough cash: order requires ${:.2}, available ${:.2}",
+                round_money(*required),
+                round_money(*available)
+            ),
+            OrderError::InsufficientShares { requested, held } => write!(
+                f,
+                "not enough shares: order sells {}, holding {}",
+                requested, held
+            ),
+            OrderError::LimitNotMet => write!(f, "limit price not met"),
+            OrderError::StopNotTriggered => write!(f, "stop price not reached"),
+            OrderError::TargetNotReached => write!(f, "take-profit price not reached"),
+            OrderError::UnknownOrder(id) => write!(f, "no open order {}", id),
+            OrderError::NotLimitOrder(id) => write!(f, "order {} has no limit price", id),
+            OrderError::AlreadyGrouped(id) => write!(f, "order {} is already in a group", id),
+            OrderError::GroupTooSmall => write!(f, "a group needs at least two orders"),
+            OrderError::TradingHalted => write!(f, "trading in the symbol is halted"),
+            OrderError::MarketClosed => write!(f, "market is closed"),
+            OrderError::RateLimited => write!(f, "too soon after the last order for the symbol"),
+            OrderError::InvalidWeights(total) => write!(f, "target weights must be non-negative and add up to at most 1, got {}", total),
+            OrderError::PositionLimitExceeded { value, limit } => write!(
+                f,
+                "position limit exceeded: position would be worth ${:.2}, limit ${:.2}",
+                round_money(*value),
+                round_money(*limit)
+            ),
+            OrderError::InvalidLotSize { quantity, lot_size } => write!(f, "quantity {} is not a multiple of the lot size {}", quantity, lot_size),
+            OrderError::InsufficientLiquidity { requested, available } => write!(
+                f,
+                "not enough liquidity: order needs {} shares, {} available",
+                requested, available
+            ),
+            OrderError::NoLiquidity => write!(f, "no shares available at the quote"),
+            OrderError::ZeroQuantity => write!(f, "order quantity is zero"),
+            OrderError::EmptySymbol => write!(f, "order has no symbol"),
+            OrderError::InvalidPrice(price) => write!(f, "invalid order price {}", price),
+        }
+    }
+}
+
+impl Error for OrderError {}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn for_dollars_buys_the_most_shares_within_the_amount() {
+        let order = Order::for_dollars("AAPL", dec!(1000), dec!(30), OrderType::Market);
+
+        assert_eq!(order.quantity, dec!(33));
+        assert!(order.quantity * dec!(30) <= dec!(1000));
+    }
+
+    #[test]
+    fn for_dollars_sells_for_a_negative_amount() {
+        let order = Order::for_dollars("AAPL", dec!(-1000), dec!(30), OrderType::Limit(dec!(30)));
+
+        assert_eq!(order.quantity, dec!(-33));
+        assert_eq!(Order::for_dollars("AAPL", dec!(1000), Decimal::ZERO, OrderType::Market).validate(), Err(OrderError::ZeroQuantity));
+    }
+
+    #[test]
+    fn portfolio_pct_buys_against_equity_and_sells_against_the_position() {
+        let quote = MarketData::new("AAPL", dec!(100));
+        let mut portfolio = Portfolio::new(dec!(6000));
+        portfolio.execute_order(&Order::for_dollars("AAPL", dec!(1000), dec!(100), OrderType::Market), &quote);
+        let market_data = [quote];
+
+        // Equity is 6000, of which the position is 1000
+        let buy = Order::for_portfolio_pct("AAPL", dec!(0.1), &portfolio, &market_data, OrderType::Market);
+        assert_eq!(buy.quantity, dec!(6));
+        let sell = Order::for_portfolio_pct("AAPL", dec!(-0.1), &portfolio, &market_data, OrderType::Market);
+        assert_eq!(sell.quantity, dec!(-1));
+        let oversell = Order::for_portfolio_pct("AAPL", dec!(-2), &portfolio, &market_data, OrderType::Market);
+        assert_eq!(oversell.quantity, dec!(-10));
+
+        let unquoted = Order::for_portfolio_pct("MSFT", dec!(0.1), &portfolio, &market_data, OrderType::Market);
+        assert_eq!(unquoted.validate(), Err(OrderError::ZeroQuantity));
+    }
+}
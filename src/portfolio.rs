@@ -0,0 +1,2043 @@
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, NaiveTime};
+use rust_decimal::prelude::{Signed, ToPrimitive};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+
+use crate::market_data::{normalize_symbol, MarketData, MarketSnapshot, SymbolMeta};
+use crate::money::round_money;
+use crate::order::{Order, OrderError, OrderGroup, OrderId, OrderStatus, OrderType, TimeInForce};
+
+pub const INITIAL_CASH: Decimal = dec!(10000.0);
+
+// How a closing fill is matched against the shares it closes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum LotMethod {
+    #[default]
+    AverageCost, // Against the position's weighted-average cost
+    Fifo, // Against the oldest open lots first
+    Lifo, // Against the newest open lots first
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lot {
+    pub quantity: Decimal, // Signed like the position it belongs to
+    pub price: Decimal,
+    pub acquired_index: u64, // Sequence number of the trade that opened the lot
+}
+
+// One closed piece of a position: a lot under FIFO or LIFO, or the whole
+// closing fill under average cost
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RealizedGain {
+    pub symbol: String,
+    pub quantity: Decimal, // Positive for a closed long, negative for a covered short
+    pub open_price: Decimal,
+    pub close_price: Decimal,
+    pub opened_index: Option<u64>, // Trade that opened the lot, None under average cost
+    pub closed_index: u64, // Trade that closed it
+    pub gain: Decimal,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    pub quantity: Decimal, // Negative when short, fractional shares allowed
+    pub avg_cost: Decimal, // Weighted-average price paid (or received, when short) per share
+    #[serde(default)]
+    pub lots: VecDeque<Lot>, // Open lots, oldest first
+}
+
+// Part of a position closed by a fill, before it is turned into a RealizedGain
+struct ClosedLot {
+    quantity: Decimal,
+    open_price: Decimal,
+    opened_index: Option<u64>,
+}
+
+impl Position {
+    // A fill first closes shares on the other side of the position, matched by
+    // the lot method, and whatever is left over opens a new lot. Under average
+    // cost, adding to the position moves the average cost to the weighted
+    // average, reducing it leaves it unchanged, and flipping to the other side
+    // starts over at the fill price. Under FIFO and LIFO the average cost is
+    // that of the lots still open. Returns the pieces of the position closed.
+    fn add_fill(&mut self, quantity: Decimal, price: Decimal, method: LotMethod, sequence: u64) -> Vec<ClosedLot> {
+        let mut closed = Vec::new();
+        let mut remaining = quantity;
+
+        while !remaining.is_zero() && !self.quantity.is_zero() && self.quantity.signum() != remaining.signum() {
+            let lot = match method {
+                LotMethod::Lifo => self.lots.back_mut(),
+                LotMethod::AverageCost | LotMethod::Fifo => self.lots.front_mut(),
+            };
+
+            // Without lot records (e.g. a position restored from an older save)
+            // the whole position is closed against its average cost
+            let (take, open_price, opened_index) = match lot {
+                Some(lot) => {
+                    let take = remaining.abs().min(lot.quantity.abs()) * lot.quantity.signum();
+                    lot.quantity -= take;
+                    (take, lot.price, Some(lot.acquired_index))
+                }
+                None => (remaining.abs().min(self.quantity.abs()) * self.quantity.signum(), self.avg_cost, None),
+            };
+            self.lots.retain(|lot| !lot.quantity.is_zero());
+
+            closed.push(ClosedLot {
+                quantity: take,
+                open_price,
+                opened_index,
+            });
+            self.quantity -= take;
+            remaining += take;
+        }
+
+        if method == LotMethod::AverageCost && !closed.is_empty() {
+            closed = vec![ClosedLot {
+                quantity: closed.iter().map(|lot| lot.quantity).sum(),
+                open_price: self.avg_cost,
+                opened_index: None,
+            }];
+        }
+
+        if !remaining.is_zero() {
+            let total_cost = self.avg_cost * self.quantity + price * remaining;
+            self.quantity += remaining;
+            self.avg_cost = total_cost / self.quantity;
+            self.lots.push_back(Lot {
+                quantity: remaining,
+                price,
+                acquired_index: sequence,
+            });
+        }
+
+        if self.quantity.is_zero() {
+            self.avg_cost = Decimal::ZERO;
+        } else if method != LotMethod::AverageCost && !self.lots.is_empty() {
+            let total_cost: Decimal = self.lots.iter().map(|lot| lot.price * lot.quantity).sum();
+            let total_quantity: Decimal = self.lots.iter().map(|lot| lot.quantity).sum();
+            self.avg_cost = total_cost / total_quantity;
+        }
+
+        closed
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum CommissionModel {
+    #[default]
+    None,
+    PerTrade(Decimal), // Flat fee per filled order
+    PerShare(Decimal), // Fee per share filled
+    Percentage(Decimal), // Fraction of the filled value, e.g. 0.001 for 0.1%
+}
+
+impl CommissionModel {
+    pub fn commission(&self, quantity: Decimal, execution_price: Decimal) -> Decimal {
+        match *self {
+            CommissionModel::None => Decimal::ZERO,
+            CommissionModel::PerTrade(fee) => fee,
+            CommissionModel::PerShare(fee) => fee * quantity.abs(),
+            CommissionModel::Percentage(rate) => rate * execution_price * quantity.abs(),
+        }
+    }
+}
+
+// Moves market fills away from the quote, against the trader
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum Slippage {
+    #[default]
+    None,
+    Fixed(Decimal), // Per-share amount added to buys and taken off sells
+    Percent(Decimal), // Fraction of the quote, e.g. 0.001 for 0.1%
+}
+
+impl Slippage {
+    pub fn fill_price(&self, quantity: Decimal, market_price: Decimal) -> Decimal {
+        let penalty = match *self {
+            Slippage::None => Decimal::ZERO,
+            Slippage::Fixed(amount) => amount,
+            Slippage::Percent(rate) => rate * market_price,
+        };
+
+        if quantity > Decimal::ZERO {
+            market_price + penalty
+        } else {
+            (market_price - penalty).max(Decimal::ZERO)
+        }
+    }
+}
+
+// Daily window in which orders may execute, open inclusive and close exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TradingSession {
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+}
+
+impl TradingSession {
+    pub fn is_open(&self, time: NaiveTime) -> bool {
+        time >= self.open && time < self.close
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trade {
+    pub sequence: u64, // Starts at 1 and increments with every fill
+    pub symbol: String,
+    pub quantity: Decimal, // Negative for sells
+    pub price: Decimal, // Execution price after slippage, or the average over the order book levels a limit swept
+    #[serde(default)]
+    pub quoted_price: Decimal, // Bid or ask quoted for the fill's side before slippage
+    pub commission: Decimal,
+    #[serde(default)]
+    pub cash_balance: Decimal, // Cash left right after the fill
+}
+
+// What Portfolio::preview_order expects submitting an order would do
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderPreview {
+    pub status: OrderStatus, // As execute_order would report it, including why it would be rejected
+    pub fill_price: Option<Decimal>, // Execution price after slippage, None unless it would fill now
+    pub quantity: Decimal, // Quantity that would fill, signed like Order::quantity
+    pub commission: Decimal,
+    pub cash_change: Decimal, // Negative for buys
+    pub position: Decimal, // Quantity held in the symbol afterwards
+}
+
+// Where an order would execute, before checking it can be paid for
+struct Execution {
+    price: Decimal, // After slippage, or averaged over the order book levels swept
+    quoted_price: Decimal,
+    available: Option<Decimal>, // Shares that can fill there, unlimited when None
+}
+
+// A fill worked out but not yet applied to the portfolio
+struct Settlement {
+    symbol: String, // Normalized
+    quantity: Decimal, // Reduced to the quoted liquidity
+    execution_price: Decimal,
+    commission: Decimal,
+    cash_change: Decimal,
+    status: OrderStatus,
+}
+
+// An order waiting on the book for its limit or stop condition to be met
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpenOrder {
+    pub id: OrderId,
+    pub order: Order,
+    pub reserved: Decimal, // Cash set aside so a buy limit can still settle when it fills
+    #[serde(default)]
+    pub group: Option<OrderGroup>, // Orders cancelled when this one fills, and vice versa
+    #[serde(default)]
+    pub trailing_mark: Option<Decimal>, // Best price a trailing stop has seen since it was placed
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Portfolio {
+    pub cash: Decimal,
+    initial_cash: Decimal, // Starting capital that profit and loss is measured against
+    pub holdings: BTreeMap<String, Position>, // Symbol to position mapping, in symbol order so output is the same every run
+    pub allow_shorting: bool, // Permit sells that take a holding below zero
+    pub commission: CommissionModel, // Fee charged against cash on every fill
+    pub slippage: Slippage, // Price penalty applied to market fills only
+    pub record_equity: bool, // Append to the equity curve on every applied snapshot
+    pub lot_method: LotMethod, // How sells are matched against earlier buys
+    pub leverage: Decimal, // 1 for a cash account; above 1 lets buys borrow, taking cash negative
+    pub margin_interest_rate: Decimal, // Annual rate charged on a negative cash balance
+    pub max_position_pct: Option<Decimal>, // Largest fraction of equity one long position may reach, e.g. 0.2
+    pub lot_size: Option<Decimal>, // Orders must be whole multiples of this, e.g. 100 for round lots; fractional shares when None
+    pub session: Option<TradingSession>, // Trading hours, enforced once the clock is set
+    pub min_order_interval: Option<Duration>, // Shortest clock time allowed between orders for one symbol
+    #[serde(default)]
+    pub min_order_ticks: Option<u64>, // Fewest market updates allowed between orders for one symbol, e.g. 5 in a stream
+    pub reinvest_dividends: bool, // Buy more of the paying symbol with each dividend instead of taking cash
+    realized: Decimal, // Running profit or loss locked in by closing positions
+    realized_gains: Vec<RealizedGain>, // Every closed piece of a position, in order
+    trades: Vec<Trade>, // Every fill, in execution order
+    open_orders: Vec<OpenOrder>, // Resting orders, oldest first
+    reserved_cash: Decimal, // Sum of the reservations held by open orders
+    next_order_id: u64,
+    next_group_id: u64,
+    equity_curve: Vec<(i64, Decimal)>, // Snapshot index or timestamp to market value
+    #[serde(skip)]
+    halted: HashSet<String>, // Symbols that can't trade until the next tick
+    clock: Option<NaiveDateTime>, // Simulated time of the latest market update
+    last_order_at: BTreeMap<String, NaiveDateTime>, // Symbol to clock time of its latest accepted order
+    #[serde(default)]
+    ticks: u64, // Market updates counted by advance_tick
+    #[serde(default)]
+    last_order_tick: BTreeMap<String, u64>, // Symbol to tick of its latest accepted order
+}
+
+impl Default for Portfolio {
+    fn default() -> Self {
+        Portfolio::new(INITIAL_CASH)
+    }
+}
+
+impl Portfolio {
+    pub fn new(initial_cash: Decimal) -> Self {
+        Portfolio {
+            cash: initial_cash,
+            initial_cash,
+            holdings: BTreeMap::new(),
+            allow_shorting: false,
+            commission: CommissionModel::None,
+            slippage: Slippage::None,
+            record_equity: false,
+            lot_method: LotMethod::AverageCost,
+            leverage: Decimal::ONE,
+            margin_interest_rate: Decimal::ZERO,
+            max_position_pct: None,
+            lot_size: None,
+            session: None,
+            min_order_interval: None,
+            min_order_ticks: None,
+            reinvest_dividends: false,
+            realized: Decimal::ZERO,
+            realized_gains: Vec::new(),
+            trades: Vec::new(),
+            open_orders: Vec::new(),
+            reserved_cash: Decimal::ZERO,
+            next_order_id: 1,
+            next_group_id: 1,
+            equity_curve: Vec::new(),
+            halted: HashSet::new(),
+            clock: None,
+            last_order_at: BTreeMap::new(),
+            ticks: 0,
+            last_order_tick: BTreeMap::new(),
+        }
+    }
+
+    // Saves the complete state, including open orders and history, as JSON
+    pub fn save_state(&self, file_path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(file_path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()
+    }
+
+    pub fn load_state(file_path: &str) -> io::Result<Portfolio> {
+        let reader = BufReader::new(File::open(file_path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    pub fn initial_cash(&self) -> Decimal {
+        self.initial_cash
+    }
+
+    // Cash not held back by resting buy orders
+    pub fn available_cash(&self) -> Decimal {
+        self.cash - self.reserved_cash
+    }
+
+    // Amount borrowed on margin, carried as a negative cash balance
+    pub fn margin_used(&self) -> Decimal {
+        (-self.cash).max(Decimal::ZERO)
+    }
+
+    // Most a new buy can spend: available cash plus what the account may still
+    // borrow against its equity at the configured leverage
+    pub fn buying_power(&self, market_data: &[MarketData]) -> Decimal {
+        self.buying_power_with(self.market_value(market_data))
+    }
+
+    pub fn open_orders(&self) -> &[OpenOrder] {
+        &self.open_orders
+    }
+
+    // Assigns the order an id and fills it if it can execute against the quote.
+    // Limit and stop orders whose condition isn't met yet are left resting on the
+    // book under that id instead, unless they are immediate-or-cancel or
+    // fill-or-kill. A fill-or-kill order is rejected outright if the quote can't
+    // fill all of it. A good-till-cancelled or day limit that only partly fills
+    // leaves the rest resting under its id. With a lot size set, an order for
+    // anything other than a whole number of lots is rejected rather than rounded.
+    // With a minimum order interval and the clock set, or a minimum number of
+    // ticks, an order for a symbol that already had one accepted less than that
+    // long ago is rejected as rate limited.
+    pub fn execute_order(&mut self, order: &Order, quote: &MarketData) -> (OrderId, OrderStatus) {
+        let id = OrderId(self.next_order_id);
+        self.next_order_id += 1;
+
+        if let Err(err) = self.check_order(order, quote) {
+            return (id, OrderStatus::Rejected(err));
+        }
+
+        let mut trailing_mark = None;
+        let status = match self.try_fill(order, quote, &mut trailing_mark) {
+            Ok(OrderStatus::PartiallyFilled(filled)) => {
+                if let Some(open_order) = self.rest_remainder(id, order, filled) {
+                    self.open_orders.push(open_order);
+                }
+                OrderStatus::PartiallyFilled(filled)
+            }
+            Ok(status) => status,
+            Err(err @ (OrderError::LimitNotMet | OrderError::StopNotTriggered | OrderError::TargetNotReached))
+                if matches!(order.time_in_force, TimeInForce::Ioc | TimeInForce::Fok) =>
+            {
+                OrderStatus::Rejected(err)
+            }
+            Err(OrderError::LimitNotMet | OrderError::StopNotTriggered | OrderError::TargetNotReached) => match self.rest_order(id, order, trailing_mark) {
+                Ok(()) => OrderStatus::Resting,
+                Err(err) => OrderStatus::Rejected(err),
+            },
+            Err(err) => OrderStatus::Rejected(err),
+        };
+        // Only an order the portfolio took on, filled or resting, starts the wait
+        if !matches!(status, OrderStatus::Rejected(_)) {
+            self.record_order_time(&order.symbol);
+        }
+
+        log::info!("order {} ({} {}): {}", id, order.quantity, order.symbol, status);
+        (id, status)
+    }
+
+    // What execute_order would do with the order against the quote, worked out
+    // without changing the portfolio: no id is used up, nothing is filled or
+    // left resting, and rate limits are untouched. A trailing stop is previewed
+    // as newly placed, so it starts its mark at the quote.
+    pub fn preview_order(&self, order: &Order, quote: &MarketData) -> OrderPreview {
+        let held = self.holdings.get(&normalize_symbol(&order.symbol)).map_or(Decimal::ZERO, |position| position.quantity);
+        let mut preview = OrderPreview {
+            status: OrderStatus::Resting,
+            fill_price: None,
+            quantity: Decimal::ZERO,
+            commission: Decimal::ZERO,
+            cash_change: Decimal::ZERO,
+            position: held,
+        };
+
+        let settlement = self.check_order(order, quote).and_then(|()| {
+            let execution = self.fill_prices(order, quote, None)?;
+            self.settle(order, execution.price, execution.available)
+        });
+        preview.status = match settlement {
+            Ok(settlement) => {
+                preview.fill_price = Some(settlement.execution_price);
+                preview.quantity = settlement.quantity;
+                preview.commission = settlement.commission;
+                preview.cash_change = settlement.cash_change;
+                preview.position = held + settlement.quantity;
+                settlement.status
+            }
+            Err(err @ (OrderError::LimitNotMet | OrderError::StopNotTriggered | OrderError::TargetNotReached))
+                if matches!(order.time_in_force, TimeInForce::Ioc | TimeInForce::Fok) =>
+            {
+                OrderStatus::Rejected(err)
+            }
+            Err(OrderError::LimitNotMet | OrderError::StopNotTriggered | OrderError::TargetNotReached) => match self.reservation(order) {
+                Ok(_) => OrderStatus::Resting,
+                Err(err) => OrderStatus::Rejected(err),
+            },
+            Err(err) => OrderStatus::Rejected(err),
+        };
+
+        preview
+    }
+
+    // The checks an order must pass before it can fill or rest, in the order
+    // execute_order has always applied them
+    fn check_order(&self, order: &Order, quote: &MarketData) -> Result<(), OrderError> {
+        order.validate()?;
+        if let Some(lot_size) = self.lot_size.filter(|lot_size| *lot_size > Decimal::ZERO) {
+            if !(order.quantity % lot_size).is_zero() {
+                return Err(OrderError::InvalidLotSize { quantity: order.quantity, lot_size });
+            }
+        }
+        if self.halted.contains(&normalize_symbol(&order.symbol)) {
+            return Err(OrderError::TradingHalted);
+        }
+        if !self.market_open() {
+            return Err(OrderError::MarketClosed);
+        }
+        if self.rate_limited(&order.symbol) {
+            return Err(OrderError::RateLimited);
+        }
+
+        if order.time_in_force == TimeInForce::Fok {
+            if let Some(available) = quote.available.filter(|available| *available < order.quantity.abs()) {
+                return Err(OrderError::InsufficientLiquidity {
+                    requested: order.quantity.abs(),
+                    available,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    // Re-evaluates every resting order against a new snapshot. Orders that fill,
+    // or that trigger but can no longer settle, leave the book and are reported;
+    // the rest keep waiting with their reservations intact. A limit that partly
+    // fills keeps its remainder resting, as in execute_order. When an order in
+    // a group fills, even partly, the others in the group are cancelled and
+    // reported too, and any remainder rests on its own.
+    //
+    // Stops are evaluated before other orders, oldest first within each kind, so
+    // when one snapshot satisfies both legs of a bracket the stop is the one that
+    // fills.
+    pub fn process_pending(&mut self, snapshot: &MarketSnapshot) -> Vec<(OrderId, OrderStatus)> {
+        self.process_pending_with(|order| snapshot.quote(&order.symbol))
+    }
+
+    // process_pending with the quote each order is evaluated against chosen
+    // per order, so a bar can show each one the price that would trigger it.
+    // Orders with no quote keep waiting.
+    pub(crate) fn process_pending_with<Q: Borrow<MarketData>>(&mut self, quote_for: impl Fn(&Order) -> Option<Q>) -> Vec<(OrderId, OrderStatus)> {
+        let mut results = Vec::new();
+        if !self.market_open() {
+            return results;
+        }
+
+        let mut filled_groups = HashSet::new();
+        let mut remaining = Vec::new();
+
+        let mut book: Vec<(usize, OpenOrder)> = std::mem::take(&mut self.open_orders).into_iter().enumerate().collect();
+        book.sort_by_key(|(_, open_order)| !is_stop(&open_order.order.order_type));
+
+        for (index, mut open_order) in book {
+            if open_order.group.is_some_and(|group| filled_groups.contains(&group)) {
+                self.reserved_cash -= open_order.reserved;
+                results.push((open_order.id, OrderStatus::Cancelled));
+                continue;
+            }
+            let Some(quote) = quote_for(&open_order.order).filter(|quote| !self.halted.contains(&quote.borrow().symbol)) else {
+                remaining.push((index, open_order));
+                continue;
+            };
+
+            // Release the reservation so the fill can spend it
+            self.reserved_cash -= open_order.reserved;
+
+            match self.try_fill(&open_order.order, quote.borrow(), &mut open_order.trailing_mark) {
+                Err(OrderError::LimitNotMet | OrderError::StopNotTriggered | OrderError::TargetNotReached) => {
+                    self.reserved_cash += open_order.reserved;
+                    remaining.push((index, open_order));
+                }
+                Ok(status) => {
+                    filled_groups.extend(open_order.group);
+                    if let OrderStatus::PartiallyFilled(filled) = status {
+                        remaining.extend(self.rest_remainder(open_order.id, &open_order.order, filled).map(|rest| (index, rest)));
+                    }
+                    results.push((open_order.id, status));
+                }
+                Err(err) => results.push((open_order.id, OrderStatus::Rejected(err))),
+            }
+        }
+
+        // Put the book back in its original order, dropping orders whose group
+        // filled after they had already been evaluated
+        remaining.sort_by_key(|(index, _)| *index);
+        for (_, open_order) in remaining {
+            if open_order.group.is_some_and(|group| filled_groups.contains(&group)) {
+                self.reserved_cash -= open_order.reserved;
+                results.push((open_order.id, OrderStatus::Cancelled));
+            } else {
+                self.open_orders.push(open_order);
+            }
+        }
+
+        for (id, status) in &results {
+            log::info!("resting order {}: {}", id, status);
+        }
+        results
+    }
+
+    // Advances the simulated clock. When a trading session is set, crossing its
+    // close, or moving on to a later date, ends the trading day and the day
+    // orders that expire are returned.
+    pub fn set_clock(&mut self, time: NaiveDateTime) -> Vec<(OrderId, OrderStatus)> {
+        let previous = self.clock.replace(time);
+
+        match (self.session, previous) {
+            (Some(session), Some(previous)) if previous <= time => {
+                let closed_today = previous.time() < session.close && time.time() >= session.close;
+                if closed_today || time.date() > previous.date() {
+                    return self.end_of_day();
+                }
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn clock(&self) -> Option<NaiveDateTime> {
+        self.clock
+    }
+
+    // Counts one market update towards min_order_ticks, for feeds such as
+    // run_stream whose quotes carry no time to set the clock from
+    pub fn advance_tick(&mut self) {
+        self.ticks += 1;
+    }
+
+    // Whether an order for the symbol came too recently for another one
+    fn rate_limited(&self, symbol: &str) -> bool {
+        let symbol = normalize_symbol(symbol);
+
+        let too_soon_by_clock = match (self.min_order_interval, self.clock) {
+            // A clock moved backwards counts as no time passing
+            (Some(interval), Some(now)) => self
+                .last_order_at
+                .get(&symbol)
+                .is_some_and(|&last| (now - last).to_std().unwrap_or_default() < interval),
+            _ => false,
+        };
+        let too_soon_by_ticks = self
+            .min_order_ticks
+            .is_some_and(|window| self.last_order_tick.get(&symbol).is_some_and(|&last| self.ticks - last < window));
+
+        too_soon_by_clock || too_soon_by_ticks
+    }
+
+    fn record_order_time(&mut self, symbol: &str) {
+        if let (Some(_), Some(now)) = (self.min_order_interval, self.clock) {
+            self.last_order_at.insert(normalize_symbol(symbol), now);
+        }
+        if self.min_order_ticks.is_some() {
+            self.last_order_tick.insert(normalize_symbol(symbol), self.ticks);
+        }
+    }
+
+    // Always open without a session or before the clock has been set
+    pub fn market_open(&self) -> bool {
+        match (self.session, self.clock) {
+            (Some(session), Some(clock)) => session.is_open(clock.time()),
+            _ => true,
+        }
+    }
+
+    // Closes the trading day: day orders still resting are removed from the book
+    // and reported as expired
+    pub fn end_of_day(&mut self) -> Vec<(OrderId, OrderStatus)> {
+        let mut expired = Vec::new();
+
+        for open_order in std::mem::take(&mut self.open_orders) {
+            if open_order.order.time_in_force == TimeInForce::Day {
+                self.reserved_cash -= open_order.reserved;
+                log::info!("day order {} expired", open_order.id);
+                expired.push((open_order.id, OrderStatus::Expired));
+            } else {
+                self.open_orders.push(open_order);
+            }
+        }
+
+        expired
+    }
+
+    // Links two or more resting orders, such as the stop and take-profit legs
+    // of a bracket, so that the first to fill cancels the others
+    pub fn group_orders(&mut self, ids: &[OrderId]) -> Result<OrderGroup, OrderError> {
+        let unique: HashSet<OrderId> = ids.iter().copied().collect();
+        if unique.len() < 2 {
+            return Err(OrderError::GroupTooSmall);
+        }
+
+        let mut indices = Vec::with_capacity(ids.len());
+        for &id in &unique {
+            let index = self.open_order_index(id)?;
+            if self.open_orders[index].group.is_some() {
+                return Err(OrderError::AlreadyGrouped(id));
+            }
+            indices.push(index);
+        }
+
+        let group = OrderGroup(self.next_group_id);
+        self.next_group_id += 1;
+        for index in indices {
+            self.open_orders[index].group = Some(group);
+        }
+
+        Ok(group)
+    }
+
+    // Advances the portfolio to a new market snapshot: resting orders are
+    // re-evaluated and, if record_equity is set, the resulting market value is
+    // added to the equity curve under the given index or timestamp.
+    pub fn apply_snapshot(&mut self, timestamp: i64, market_data: &[MarketData]) -> Vec<(OrderId, OrderStatus)> {
+        let results = self.process_pending(&MarketSnapshot::new(market_data));
+        self.mark_equity(timestamp, market_data);
+
+        results
+    }
+
+    // Adds the current market value to the equity curve if record_equity is set
+    pub(crate) fn mark_equity(&mut self, timestamp: i64, market_data: &[MarketData]) {
+        if self.record_equity {
+            let value = self.market_value(market_data);
+            self.equity_curve.push((timestamp, value));
+        }
+    }
+
+    // Replaces the set of halted symbols. Orders for a halted symbol are
+    // rejected and its resting orders wait until it trades again.
+    pub fn set_halted(&mut self, symbols: HashSet<String>) {
+        self.halted = symbols.iter().map(|symbol| normalize_symbol(symbol)).collect();
+    }
+
+    pub fn equity_curve(&self) -> &[(i64, Decimal)] {
+        &self.equity_curve
+    }
+
+    // Removes a resting order and releases any cash it reserved
+    pub fn cancel_order(&mut self, id: OrderId) -> Result<Order, OrderError> {
+        let index = self.open_order_index(id)?;
+        let open_order = self.open_orders.remove(index);
+        self.reserved_cash -= open_order.reserved;
+
+        Ok(open_order.order)
+    }
+
+    // Changes the quantity, and optionally the limit price, of a resting order in
+    // place. The reservation is recomputed, and the order is left untouched if the
+    // new reservation can't be covered.
+    pub fn modify_order(&mut self, id: OrderId, new_quantity: Decimal, new_limit: Option<Decimal>) -> Result<(), OrderError> {
+        let index = self.open_order_index(id)?;
+
+        let mut order = self.open_orders[index].order.clone();
+        order.quantity = new_quantity;
+        if let Some(new_limit) = new_limit {
+            match &mut order.order_type {
+                OrderType::Limit(limit) | OrderType::StopLimit { limit, .. } => *limit = new_limit,
+                _ => return Err(OrderError::NotLimitOrder(id)),
+            }
+        }
+        order.validate()?;
+
+        let old_reserved = self.open_orders[index].reserved;
+        let reserved = self.reservation_for(&order);
+        let available = self.available_cash() + old_reserved;
+        if reserved > available {
+            return Err(OrderError::InsufficientCash {
+                required: reserved,
+                available,
+            });
+        }
+
+        self.reserved_cash += reserved - old_reserved;
+        self.open_orders[index].order = order;
+        self.open_orders[index].reserved = reserved;
+
+        Ok(())
+    }
+
+    fn open_order_index(&self, id: OrderId) -> Result<usize, OrderError> {
+        self.open_orders
+            .iter()
+            .position(|open_order| open_order.id == id)
+            .ok_or(OrderError::UnknownOrder(id))
+    }
+
+    fn rest_order(&mut self, id: OrderId, order: &Order, trailing_mark: Option<Decimal>) -> Result<(), OrderError> {
+        let reserved = self.reservation(order)?;
+
+        self.reserved_cash += reserved;
+        self.open_orders.push(OpenOrder {
+            id,
+            order: order.clone(),
+            reserved,
+            group: None,
+            trailing_mark,
+        });
+
+        Ok(())
+    }
+
+    // The unfilled part of a partly filled order, left resting under the same id
+    // when it may still fill at its price later: a good-till-cancelled or day
+    // order with a limit, a triggered stop-limit resting as a plain limit.
+    // Immediate-or-cancel orders and those that filled at market drop it, as
+    // does an order whose remainder can no longer be reserved for.
+    fn rest_remainder(&mut self, id: OrderId, order: &Order, filled: Decimal) -> Option<OpenOrder> {
+        let limit = match order.order_type {
+            OrderType::Limit(limit) | OrderType::StopLimit { limit, .. } => limit,
+            _ => return None,
+        };
+        if !matches!(order.time_in_force, TimeInForce::Gtc | TimeInForce::Day) {
+            return None;
+        }
+
+        let remainder = Order {
+            quantity: order.quantity - filled,
+            order_type: OrderType::Limit(limit),
+            ..order.clone()
+        };
+        match self.reservation(&remainder) {
+            Ok(reserved) => {
+                self.reserved_cash += reserved;
+                Some(OpenOrder {
+                    id,
+                    order: remainder,
+                    reserved,
+                    group: None,
+                    trailing_mark: None,
+                })
+            }
+            Err(err) => {
+                log::warn!("order {}: dropping the unfilled {}: {}", id, remainder.quantity, err);
+                None
+            }
+        }
+    }
+
+    // The cash a resting order would hold back, if there's enough of it
+    fn reservation(&self, order: &Order) -> Result<Decimal, OrderError> {
+        let reserved = self.reservation_for(order);
+        if reserved > self.available_cash() {
+            return Err(OrderError::InsufficientCash {
+                required: reserved,
+                available: self.available_cash(),
+            });
+        }
+
+        Ok(reserved)
+    }
+
+    // Buy limits reserve their worst-case cost at the limit price, including
+    // commission. Other orders have no known fill price, so reserve nothing.
+    fn reservation_for(&self, order: &Order) -> Decimal {
+        match order.order_type {
+            OrderType::Limit(limit) | OrderType::StopLimit { limit, .. } if order.quantity > Decimal::ZERO => {
+                limit * order.quantity + self.commission.commission(order.quantity, limit)
+            }
+            _ => Decimal::ZERO,
+        }
+    }
+
+    // Fills the order if it can execute against the quote. A trailing stop
+    // first moves its mark, the best price seen by this order alone, which
+    // leaves the book with the order when it fills, is cancelled or expires.
+    fn try_fill(&mut self, order: &Order, quote: &MarketData, trailing_mark: &mut Option<Decimal>) -> Result<OrderStatus, OrderError> {
+        if matches!(order.order_type, OrderType::TrailingStop { .. }) {
+            *trailing_mark = Some(next_trailing_mark(order, *trailing_mark, quote.fill_price(order.quantity)));
+        }
+
+        let execution = self.fill_prices(order, quote, *trailing_mark)?;
+        self.process_order(order, execution.price, execution.quoted_price, execution.available)
+    }
+
+    // Where the order would execute against the quote, or why it can't yet.
+    // A limit with an order book to trade against sweeps the levels within its
+    // limit at their own prices instead, filling at their average and no more
+    // than they hold.
+    fn fill_prices(&self, order: &Order, quote: &MarketData, trailing_mark: Option<Decimal>) -> Result<Execution, OrderError> {
+        let market_price = quote.fill_price(order.quantity);
+        let market_fill_price = self.slippage.fill_price(order.quantity, market_price);
+        let limit_fill = |limit: Decimal| match quote.depth.as_ref().filter(|book| !book.side(order.quantity).is_empty()) {
+            Some(book) => book.sweep(order.quantity, limit).map(|(price, filled)| (price, Some(filled))).ok_or(OrderError::LimitNotMet),
+            None if limit_met(order, market_price, limit) => Ok((limit, quote.available)),
+            None => Err(OrderError::LimitNotMet),
+        };
+
+        let (price, available) = match order.order_type {
+            // Execute market order at the current market price, less slippage
+            OrderType::Market => (market_fill_price, quote.available),
+            // Execute limit order if the market price is favorable
+            OrderType::Limit(limit_price) => limit_fill(limit_price)?,
+            OrderType::Stop(stop_price) => {
+                if stop_triggered(order, market_price, stop_price) {
+                    // A triggered stop becomes a market order
+                    (market_fill_price, quote.available)
+                } else {
+                    return Err(OrderError::StopNotTriggered);
+                }
+            }
+            OrderType::TakeProfit(target) => {
+                if target_reached(order, market_price, target) {
+                    // Like a stop, a reached target becomes a market order
+                    (market_fill_price, quote.available)
+                } else {
+                    return Err(OrderError::TargetNotReached);
+                }
+            }
+            OrderType::StopLimit { stop, limit } => {
+                if stop_triggered(order, market_price, stop) {
+                    // A triggered stop-limit becomes a limit order
+                    limit_fill(limit)?
+                } else {
+                    return Err(OrderError::StopNotTriggered);
+                }
+            }
+            OrderType::TrailingStop { offset } => {
+                let mark = next_trailing_mark(order, trailing_mark, market_price);
+                let triggered = if order.quantity < Decimal::ZERO { market_price <= mark - offset } else { market_price >= mark + offset };
+                if triggered {
+                    (market_fill_price, quote.available)
+                } else {
+                    return Err(OrderError::StopNotTriggered);
+                }
+            }
+        };
+
+        Ok(Execution {
+            price,
+            quoted_price: market_price,
+            available,
+        })
+    }
+
+    // Fills as much of the order as the quoted liquidity allows. Cash, holdings
+    // and the trade log all use the quantity actually filled, and a remainder is
+    // reported through OrderStatus::PartiallyFilled for the caller to rest or drop.
+    fn process_order(&mut self, order: &Order, execution_price: Decimal, quoted_price: Decimal, available: Option<Decimal>) -> Result<OrderStatus, OrderError> {
+        let settlement = self.settle(order, execution_price, available)?;
+
+        if !settlement.quantity.is_zero() {
+            self.cash += settlement.cash_change;
+            self.fill(&settlement.symbol, settlement.quantity, execution_price, quoted_price, settlement.commission);
+        }
+        Ok(settlement.status)
+    }
+
+    // Works out what filling the order at execution_price would settle to,
+    // rejecting it when the cash, shares or position limit don't allow it, or
+    // when it is fill-or-kill and the liquidity at its price, such as the book
+    // levels within its limit, can't fill all of it
+    fn settle(&self, order: &Order, execution_price: Decimal, available: Option<Decimal>) -> Result<Settlement, OrderError> {
+        if let Some(available) = available.filter(|available| order.time_in_force == TimeInForce::Fok && *available < order.quantity.abs()) {
+            return Err(OrderError::InsufficientLiquidity {
+                requested: order.quantity.abs(),
+                available,
+            });
+        }
+
+        let quantity = match available {
+            Some(available) if available < order.quantity.abs() => available.max(Decimal::ZERO) * order.quantity.signum(),
+            _ => order.quantity,
+        };
+        if quantity.is_zero() && !order.quantity.is_zero() {
+            return Err(OrderError::NoLiquidity);
+        }
+
+        let symbol = normalize_symbol(&order.symbol);
+        let total_order_value = execution_price * quantity.abs();
+        let commission = round_money(self.commission.commission(quantity, execution_price));
+
+        let cash_change = if quantity > Decimal::ZERO {
+            // Buying stocks, which also covers any short position. Without a
+            // quote for every holding, equity is estimated at cost.
+            let equity = self.book_value();
+            self.check_position_limit(&symbol, quantity, execution_price, equity)?;
+
+            // Cash moves by whole cents; the trade keeps the unrounded price
+            let cost = round_money(total_order_value + commission);
+            let buying_power = self.buying_power_with(equity);
+            if buying_power < cost {
+                return Err(OrderError::InsufficientCash {
+                    required: cost,
+                    available: buying_power,
+                });
+            }
+            -cost
+        } else if quantity < Decimal::ZERO {
+            // Selling stocks, or selling short when shorting is allowed
+            let held = self.holdings.get(&symbol).map_or(Decimal::ZERO, |position| position.quantity);
+
+            if !(self.allow_shorting || held >= -quantity) {
+                return Err(OrderError::InsufficientShares {
+                    requested: -quantity,
+                    held,
+                });
+            }
+            round_money(total_order_value - commission)
+        } else {
+            Decimal::ZERO
+        };
+
+        let status = if quantity == order.quantity {
+            OrderStatus::Filled
+        } else {
+            OrderStatus::PartiallyFilled(quantity)
+        };
+        Ok(Settlement {
+            symbol,
+            quantity,
+            execution_price,
+            commission,
+            cash_change,
+            status,
+        })
+    }
+
+    // Rejects, rather than shrinks, a buy that would take the position above
+    // max_position_pct of equity, valuing the position at the fill price
+    fn check_position_limit(&self, symbol: &str, quantity: Decimal, execution_price: Decimal, equity: Decimal) -> Result<(), OrderError> {
+        let Some(max_position_pct) = self.max_position_pct else {
+            return Ok(());
+        };
+
+        let held = self.holdings.get(symbol).map_or(Decimal::ZERO, |position| position.quantity);
+        let value = (held + quantity) * execution_price;
+        let limit = max_position_pct * equity.max(Decimal::ZERO);
+        if value > limit {
+            return Err(OrderError::PositionLimitExceeded { value, limit });
+        }
+
+        Ok(())
+    }
+
+    fn buying_power_with(&self, equity: Decimal) -> Decimal {
+        let borrowable = (self.leverage.max(Decimal::ONE) - Decimal::ONE) * equity.max(Decimal::ZERO);
+        (self.available_cash() + borrowable).max(Decimal::ZERO)
+    }
+
+    // Cash plus every position valued at its average cost
+    pub(crate) fn book_value(&self) -> Decimal {
+        self.cash + self.holdings.values().map(|position| position.quantity * position.avg_cost).sum::<Decimal>()
+    }
+
+    // Updates the position and realized gains for a fill and logs the trade
+    fn fill(&mut self, symbol: &str, quantity: Decimal, execution_price: Decimal, quoted_price: Decimal, commission: Decimal) {
+        let sequence = self.trades.len() as u64 + 1;
+        let position = self.holdings.entry(symbol.to_string()).or_default();
+
+        for closed in position.add_fill(quantity, execution_price, self.lot_method, sequence) {
+            let gain = (execution_price - closed.open_price) * closed.quantity;
+            self.realized += gain;
+            self.realized_gains.push(RealizedGain {
+                symbol: symbol.to_string(),
+                quantity: closed.quantity,
+                open_price: closed.open_price,
+                close_price: execution_price,
+                opened_index: closed.opened_index,
+                closed_index: sequence,
+                gain,
+            });
+        }
+
+        log::debug!("trade {}: {} {} @ {} (commission {})", sequence, quantity, symbol, execution_price, commission);
+        self.trades.push(Trade {
+            sequence,
+            symbol: symbol.to_string(),
+            quantity,
+            price: execution_price,
+            quoted_price,
+            commission,
+            cash_balance: self.cash,
+        });
+    }
+
+    pub fn transactions(&self) -> &[Trade] {
+        &self.trades
+    }
+
+    pub fn transactions_for(&self, symbol: &str) -> Vec<&Trade> {
+        let symbol = normalize_symbol(symbol);
+        self.trades.iter().filter(|trade| trade.symbol == symbol).collect()
+    }
+
+    // Order::for_dollars, with a sell capped at the long quantity held so a
+    // dollar amount larger than the position closes it rather than going short
+    pub fn order_for_dollars(&self, symbol: &str, dollars: Decimal, market_price: Decimal, order_type: OrderType) -> Order {
+        let mut order = Order::for_dollars(symbol, dollars, market_price, order_type);
+        if order.quantity < Decimal::ZERO {
+            let held = self.holdings.get(&normalize_symbol(symbol)).map_or(Decimal::ZERO, |position| position.quantity);
+            order.quantity = order.quantity.max(-held.max(Decimal::ZERO));
+        }
+        order
+    }
+
+    // Credits the dividend for every share held, or debits it from a short.
+    // A symbol that isn't held, or is held flat, is left alone. With
+    // reinvest_dividends set, a long position's dividend instead buys
+    // amount / price more shares at the given price, commission free; since
+    // quantities are fractional the whole amount is reinvested and no cash is
+    // left over. Without a positive price the dividend is paid in cash.
+    pub fn apply_dividend(&mut self, symbol: &str, per_share: Decimal, price: Option<Decimal>) {
+        let symbol = normalize_symbol(symbol);
+        let Some(position) = self.holdings.get(&symbol) else {
+            return;
+        };
+        let amount = per_share * position.quantity;
+
+        match price {
+            Some(price) if self.reinvest_dividends && amount > Decimal::ZERO && price > Decimal::ZERO => {
+                log::info!("reinvesting dividend of {} in {} @ {}", amount, symbol, price);
+                self.fill(&symbol, amount / price, price, price, Decimal::ZERO);
+            }
+            _ => self.cash += round_money(amount),
+        }
+    }
+
+    // Scales the held quantity by the split ratio (2 for a 2-for-1, 1.5 for a
+    // 3-for-2) and the average cost down by the same factor, so the position's
+    // cost and value are unchanged. Since quantities are fractional the result is
+    // kept exactly, e.g. 3 shares become 4.5 in a 3-for-2, rather than rounding
+    // and paying cash in lieu. Ratios that aren't positive are ignored.
+    pub fn apply_split(&mut self, symbol: &str, ratio: Decimal) {
+        if ratio <= Decimal::ZERO {
+            return;
+        }
+
+        if let Some(position) = self.holdings.get_mut(&normalize_symbol(symbol)) {
+            position.quantity *= ratio;
+            position.avg_cost /= ratio;
+            for lot in &mut position.lots {
+                lot.quantity *= ratio;
+                lot.price /= ratio;
+            }
+        }
+    }
+
+    // Cash plus every holding marked to its current market price. A holding
+    // with no matching market data is valued at zero, with a warning.
+    pub fn market_value(&self, current_market_data: &[MarketData]) -> Decimal {
+        let mut total_value = self.cash;
+        let mut valued = HashSet::new();
+
+        for data in current_market_data {
+            if let Some(position) = self.holdings.get(&data.symbol) {
+                // Only the first quote for a symbol counts, as in find_market_price
+                if valued.insert(data.symbol.as_str()) {
+                    total_value += data.price * position.quantity;
+                }
+            }
+        }
+
+        for (symbol, position) in &self.holdings {
+            if !position.quantity.is_zero() && !valued.contains(symbol.as_str()) {
+                log::warn!("no market data for {}, valuing it at $0.00", symbol);
+            }
+        }
+
+        total_value
+    }
+
+    pub fn calculate_profit_loss(&self, current_market_data: &[MarketData]) -> Decimal {
+        // Total portfolio value - initial cash gives profit or loss
+        self.market_value(current_market_data) - self.initial_cash
+    }
+
+    // Profit or loss locked in so far by selling longs or covering shorts
+    pub fn realized_pl(&self) -> Decimal {
+        self.realized
+    }
+
+    pub fn realized_gains(&self) -> &[RealizedGain] {
+        &self.realized_gains
+    }
+
+    // Unrealized gain or loss per open position, from its average cost to the
+    // current market price. Shorts gain when the price falls below their cost.
+    pub fn pl_by_symbol(&self, current_market_data: &[MarketData]) -> BTreeMap<String, Decimal> {
+        let mut pl = BTreeMap::new();
+
+        for data in current_market_data {
+            if let Some(position) = self.holdings.get(&data.symbol) {
+                if !position.quantity.is_zero() {
+                    pl.entry(data.symbol.clone()).or_insert((data.price - position.avg_cost) * position.quantity);
+                }
+            }
+        }
+
+        pl
+    }
+
+    // Total gain or loss on open positions that hasn't been locked in yet
+    pub fn unrealized_pl(&self, current_market_data: &[MarketData]) -> Decimal {
+        self.pl_by_symbol(current_market_data).values().sum()
+    }
+
+    // Fraction of the portfolio's market value held in each sector, e.g. 0.25
+    // for a quarter. Cash isn't a sector, so the fractions only add up to one
+    // when everything is invested. Positions without metadata are grouped
+    // under "Unknown"; those without a quote count for nothing, as in
+    // market_value.
+    pub fn sector_allocation(&self, current_market_data: &[MarketData], meta: &HashMap<String, SymbolMeta>) -> BTreeMap<String, f32> {
+        let total = self.market_value(current_market_data);
+        let snapshot = MarketSnapshot::new(current_market_data);
+        let mut values: BTreeMap<String, Decimal> = BTreeMap::new();
+
+        for (symbol, position) in &self.holdings {
+            let Some(price) = snapshot.price(symbol) else {
+                continue;
+            };
+            if position.quantity.is_zero() {
+                continue;
+            }
+
+            let sector = meta.get(symbol).map_or("Unknown", |meta| meta.sector.as_str());
+            *values.entry(sector.to_string()).or_default() += price * position.quantity;
+        }
+
+        values
+            .into_iter()
+            .filter_map(|(sector, value)| {
+                let fraction = if total.is_zero() { Decimal::ZERO } else { value / total };
+                Some((sector, fraction.to_f32()?))
+            })
+            .collect()
+    }
+}
+
+// A trailing stop's mark starts at the first price observed for the order and
+// then follows the highest price for a sell (or the lowest for a buy covering a
+// short), so the order can never fire on its first observation unless the
+// offset is zero. This is the mark after seeing market_price.
+fn next_trailing_mark(order: &Order, mark: Option<Decimal>, market_price: Decimal) -> Decimal {
+    let mark = mark.unwrap_or(market_price);
+
+    if order.quantity < Decimal::ZERO {
+        mark.max(market_price)
+    } else {
+        mark.min(market_price)
+    }
+}
+
+fn is_stop(order_type: &OrderType) -> bool {
+    matches!(order_type, OrderType::Stop(_) | OrderType::StopLimit { .. } | OrderType::TrailingStop { .. })
+}
+
+// Buys fill at or below the limit, sells at or above it
+fn limit_met(order: &Order, market_price: Decimal, limit_price: Decimal) -> bool {
+    (order.quantity > Decimal::ZERO && market_price <= limit_price) || (order.quantity < Decimal::ZERO && market_price >= limit_price)
+}
+
+// Sell stops (protecting a long) trigger when the price falls to the stop,
+// buy stops (protecting a short) when it rises to it
+fn stop_triggered(order: &Order, market_price: Decimal, stop_price: Decimal) -> bool {
+    (order.quantity < Decimal::ZERO && market_price <= stop_price) || (order.quantity > Decimal::ZERO && market_price >= stop_price)
+}
+
+// The mirror image of a stop: sell targets (closing a long) are reached when the
+// price rises to the target, buy targets (closing a short) when it falls to it
+fn target_reached(order: &Order, market_price: Decimal, target_price: Decimal) -> bool {
+    (order.quantity < Decimal::ZERO && market_price >= target_price) || (order.quantity > Decimal::ZERO && market_price <= target_price)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::bars::Ohlc;
+    use crate::market_data::{read_market_data, BookLevel, CsvOptions, InvalidPricePolicy, OrderBook};
+    use crate::order::TimeInForce;
+
+    fn limit_order(symbol: &str, quantity: Decimal, limit: Decimal) -> Order {
+        Order {
+            symbol: symbol.to_string(),
+            quantity,
+            order_type: OrderType::Limit(limit),
+            time_in_force: TimeInForce::Gtc,
+        }
+    }
+
+    fn long_portfolio(symbol: &str, quantity: Decimal, price: Decimal) -> Portfolio {
+        let mut portfolio = Portfolio::default();
+        let buy = Order {
+            symbol: symbol.to_string(),
+            quantity,
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        };
+        portfolio.execute_order(&buy, &MarketData::new(symbol, price));
+        portfolio
+    }
+
+    #[test]
+    fn buy_limit_fills_at_limit_when_price_is_below() {
+        let mut portfolio = Portfolio::default();
+
+        let (_, status) = portfolio.execute_order(&limit_order("AAPL", dec!(10), dec!(150)), &MarketData::new("AAPL", dec!(145)));
+
+        assert_eq!(status, OrderStatus::Filled);
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(10));
+        assert_eq!(portfolio.cash, dec!(8500));
+    }
+
+    #[test]
+    fn buy_limit_rests_when_price_is_above() {
+        let mut portfolio = Portfolio::default();
+
+        let (_, status) = portfolio.execute_order(&limit_order("AAPL", dec!(10), dec!(150)), &MarketData::new("AAPL", dec!(155)));
+
+        assert_eq!(status, OrderStatus::Resting);
+        assert!(!portfolio.holdings.contains_key("AAPL"));
+        assert_eq!(portfolio.cash, dec!(10000));
+        assert_eq!(portfolio.available_cash(), dec!(8500));
+    }
+
+    #[test]
+    fn sell_limit_fills_at_limit_when_price_is_above() {
+        let mut portfolio = long_portfolio("AAPL", dec!(10), dec!(100));
+
+        let (_, status) = portfolio.execute_order(&limit_order("AAPL", dec!(-4), dec!(120)), &MarketData::new("AAPL", dec!(125)));
+
+        assert_eq!(status, OrderStatus::Filled);
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(6));
+        assert_eq!(portfolio.cash, dec!(9480));
+        assert_eq!(portfolio.realized_pl(), dec!(80));
+    }
+
+    #[test]
+    fn sell_limit_rests_when_price_is_below() {
+        let mut portfolio = long_portfolio("AAPL", dec!(10), dec!(100));
+
+        let (_, status) = portfolio.execute_order(&limit_order("AAPL", dec!(-4), dec!(120)), &MarketData::new("AAPL", dec!(115)));
+
+        assert_eq!(status, OrderStatus::Resting);
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(10));
+        assert_eq!(portfolio.cash, dec!(9000));
+    }
+
+    fn market_order(symbol: &str, quantity: Decimal) -> Order {
+        Order {
+            symbol: symbol.to_string(),
+            quantity,
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        }
+    }
+
+    #[test]
+    fn a_short_can_be_opened_added_to_and_covered() {
+        let mut portfolio = Portfolio {
+            allow_shorting: true,
+            ..Portfolio::default()
+        };
+
+        // Selling short credits the proceeds
+        portfolio.execute_order(&market_order("AAPL", dec!(-10)), &MarketData::new("AAPL", dec!(100)));
+        assert_eq!(portfolio.cash, dec!(11000));
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(-10));
+        assert_eq!(portfolio.holdings["AAPL"].avg_cost, dec!(100));
+        assert_eq!(portfolio.realized_pl(), Decimal::ZERO);
+
+        // Adding to it averages the entry price
+        portfolio.execute_order(&market_order("AAPL", dec!(-10)), &MarketData::new("AAPL", dec!(90)));
+        assert_eq!(portfolio.cash, dec!(11900));
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(-20));
+        assert_eq!(portfolio.holdings["AAPL"].avg_cost, dec!(95));
+        assert_eq!(portfolio.realized_pl(), Decimal::ZERO);
+
+        // Buying back below the average locks in the difference
+        let (_, status) = portfolio.execute_order(&market_order("AAPL", dec!(20)), &MarketData::new("AAPL", dec!(80)));
+        assert_eq!(status, OrderStatus::Filled);
+        assert_eq!(portfolio.cash, dec!(10300));
+        assert_eq!(portfolio.holdings["AAPL"].quantity, Decimal::ZERO);
+        assert_eq!(portfolio.realized_pl(), dec!(300));
+    }
+
+    #[test]
+    fn a_short_sale_is_rejected_unless_shorting_is_allowed() {
+        let mut portfolio = long_portfolio("AAPL", dec!(5), dec!(100));
+
+        let (_, status) = portfolio.execute_order(&market_order("AAPL", dec!(-10)), &MarketData::new("AAPL", dec!(100)));
+        assert_eq!(
+            status,
+            OrderStatus::Rejected(OrderError::InsufficientShares {
+                requested: dec!(10),
+                held: dec!(5),
+            })
+        );
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(5));
+        assert_eq!(portfolio.cash, dec!(9500));
+    }
+
+    #[test]
+    fn selling_moves_the_gain_from_unrealized_to_realized() {
+        let mut portfolio = long_portfolio("AAPL", dec!(10), dec!(100));
+        let market_data = [MarketData::new("AAPL", dec!(120))];
+        assert_eq!(portfolio.unrealized_pl(&market_data), dec!(200));
+        assert_eq!(portfolio.realized_pl(), Decimal::ZERO);
+
+        portfolio.execute_order(&market_order("AAPL", dec!(-10)), &market_data[0]);
+
+        assert_eq!(portfolio.unrealized_pl(&market_data), Decimal::ZERO);
+        assert_eq!(portfolio.realized_pl(), dec!(200));
+        assert_eq!(portfolio.calculate_profit_loss(&market_data), dec!(200));
+    }
+
+    #[test]
+    fn each_commission_model_charges_its_fee() {
+        assert_eq!(CommissionModel::None.commission(dec!(-10), dec!(50)), Decimal::ZERO);
+        assert_eq!(CommissionModel::PerTrade(dec!(1)).commission(dec!(-10), dec!(50)), dec!(1));
+        assert_eq!(CommissionModel::PerShare(dec!(0.01)).commission(dec!(-10), dec!(50)), dec!(0.1));
+        assert_eq!(CommissionModel::Percentage(dec!(0.001)).commission(dec!(-10), dec!(50)), dec!(0.5));
+
+        // Both sides pay: the buy costs 501 and the sell nets 599
+        let mut portfolio = Portfolio {
+            commission: CommissionModel::PerTrade(dec!(1)),
+            ..Portfolio::new(dec!(1000))
+        };
+        portfolio.execute_order(&market_order("AAPL", dec!(10)), &MarketData::new("AAPL", dec!(50)));
+        assert_eq!(portfolio.cash, dec!(499));
+        portfolio.execute_order(&market_order("AAPL", dec!(-10)), &MarketData::new("AAPL", dec!(60)));
+        assert_eq!(portfolio.cash, dec!(1098));
+    }
+
+    #[test]
+    fn commission_can_make_an_affordable_buy_unaffordable() {
+        let quote = MarketData::new("AAPL", dec!(100));
+        let mut portfolio = Portfolio::new(dec!(1000));
+        assert_eq!(portfolio.clone().execute_order(&market_order("AAPL", dec!(10)), &quote).1, OrderStatus::Filled);
+
+        portfolio.commission = CommissionModel::PerTrade(dec!(1));
+        let (_, status) = portfolio.execute_order(&market_order("AAPL", dec!(10)), &quote);
+
+        let err = OrderError::InsufficientCash { required: dec!(1001), available: dec!(1000) };
+        assert_eq!(status, OrderStatus::Rejected(err));
+        assert_eq!(portfolio.cash, dec!(1000));
+    }
+
+    #[test]
+    fn profit_and_loss_is_measured_against_the_starting_cash() {
+        let mut portfolio = Portfolio::new(dec!(50000));
+        portfolio.execute_order(&market_order("AAPL", dec!(100)), &MarketData::new("AAPL", dec!(200)));
+
+        let market_data = [MarketData::new("AAPL", dec!(210))];
+        assert_eq!(portfolio.initial_cash(), dec!(50000));
+        assert_eq!(portfolio.calculate_profit_loss(&market_data), dec!(1000));
+        assert_eq!(Portfolio::default().initial_cash(), INITIAL_CASH);
+    }
+
+    #[test]
+    fn fractional_shares_can_be_bought_and_partly_sold() {
+        let mut portfolio = long_portfolio("AAPL", dec!(2.5), dec!(100));
+        assert_eq!(portfolio.cash, dec!(9750));
+
+        portfolio.execute_order(&market_order("AAPL", dec!(-1.25)), &MarketData::new("AAPL", dec!(120)));
+
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(1.25));
+        assert_eq!(portfolio.holdings["AAPL"].avg_cost, dec!(100));
+        assert_eq!(portfolio.cash, dec!(9900));
+        assert_eq!(portfolio.realized_pl(), dec!(25));
+    }
+
+    #[test]
+    fn orders_past_the_available_liquidity_fill_partially() {
+        let mut portfolio = Portfolio::default();
+        let quote = MarketData {
+            available: Some(dec!(4)),
+            ..MarketData::new("AAPL", dec!(100))
+        };
+
+        let (_, status) = portfolio.execute_order(&market_order("AAPL", dec!(10)), &quote);
+        assert_eq!(status, OrderStatus::PartiallyFilled(dec!(4)));
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(4));
+        assert_eq!(portfolio.cash, dec!(9600));
+
+        let (_, status) = portfolio.execute_order(&market_order("AAPL", dec!(-10)), &MarketData { available: Some(dec!(3)), ..quote });
+        assert_eq!(status, OrderStatus::PartiallyFilled(dec!(-3)));
+        assert_eq!(portfolio.transactions().last().unwrap().quantity, dec!(-3));
+        assert_eq!(portfolio.cash, dec!(9900));
+    }
+
+    #[test]
+    fn slippage_moves_market_fills_against_the_trader() {
+        let quote = MarketData::new("AAPL", dec!(100));
+        let mut portfolio = Portfolio {
+            slippage: Slippage::Fixed(dec!(0.05)),
+            ..Portfolio::default()
+        };
+
+        portfolio.execute_order(&market_order("AAPL", dec!(10)), &quote);
+        assert_eq!(portfolio.transactions()[0].price, dec!(100.05));
+        portfolio.execute_order(&market_order("AAPL", dec!(-10)), &quote);
+        assert_eq!(portfolio.transactions()[1].price, dec!(99.95));
+
+        portfolio.slippage = Slippage::Percent(dec!(0.001));
+        portfolio.execute_order(&market_order("AAPL", dec!(10)), &quote);
+        assert_eq!(portfolio.transactions()[2].price, dec!(100.1));
+
+        // Limit fills are left at the limit
+        portfolio.execute_order(&limit_order("AAPL", dec!(-10), dec!(99)), &quote);
+        assert_eq!(portfolio.transactions()[3].price, dec!(99));
+    }
+
+    #[test]
+    fn a_round_trip_at_an_unchanged_mid_loses_the_spread() {
+        let quote = MarketData {
+            bid: Some(dec!(99.90)),
+            ask: Some(dec!(100.10)),
+            ..MarketData::new("AAPL", dec!(100))
+        };
+        let mut portfolio = Portfolio::default();
+
+        portfolio.execute_order(&market_order("AAPL", dec!(10)), &quote);
+        portfolio.execute_order(&market_order("AAPL", dec!(-10)), &quote);
+
+        assert_eq!(portfolio.transactions()[0].price, dec!(100.10));
+        assert_eq!(portfolio.transactions()[1].price, dec!(99.90));
+        assert_eq!(portfolio.cash, INITIAL_CASH - dec!(2));
+        assert_eq!(portfolio.realized_pl(), dec!(-2));
+    }
+
+    #[test]
+    fn splits_scale_the_position_without_changing_its_cost() {
+        let mut portfolio = long_portfolio("AAPL", dec!(10), dec!(100));
+        portfolio.apply_split("AAPL", dec!(2));
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(20));
+        assert_eq!(portfolio.holdings["AAPL"].avg_cost, dec!(50));
+
+        // 3-for-2 keeps the fractional share rather than paying it out
+        let mut portfolio = long_portfolio("MSFT", dec!(3), dec!(90));
+        portfolio.apply_split("msft", dec!(1.5));
+        assert_eq!(portfolio.holdings["MSFT"].quantity, dec!(4.5));
+        assert_eq!(portfolio.holdings["MSFT"].avg_cost, dec!(60));
+        assert_eq!(portfolio.market_value(&[MarketData::new("MSFT", dec!(60))]), INITIAL_CASH);
+    }
+
+    #[test]
+    fn saved_state_loads_back_with_its_open_orders() {
+        let mut portfolio = long_portfolio("AAPL", dec!(10), dec!(100));
+        portfolio.commission = CommissionModel::PerShare(dec!(0.01));
+        let quote = MarketData::new("AAPL", dec!(105));
+        portfolio.execute_order(&limit_order("AAPL", dec!(5), dec!(95.5)), &quote);
+        portfolio.execute_order(&trailing_stop("AAPL", dec!(-10), dec!(2), TimeInForce::Day), &quote);
+        assert_eq!(portfolio.open_orders().len(), 2);
+
+        let path = std::env::temp_dir().join(format!("portfolio_state_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        portfolio.save_state(path).unwrap();
+        let loaded = Portfolio::load_state(path);
+        std::fs::remove_file(path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded, portfolio);
+        assert_eq!(loaded.available_cash(), portfolio.available_cash());
+    }
+
+    #[test]
+    fn partial_lot_sells_follow_the_lot_method() {
+        let sell_across_lots = |lot_method| {
+            let mut portfolio = Portfolio { lot_method, ..Portfolio::default() };
+            portfolio.execute_order(&market_order("AAPL", dec!(10)), &MarketData::new("AAPL", dec!(100)));
+            portfolio.execute_order(&market_order("AAPL", dec!(10)), &MarketData::new("AAPL", dec!(120)));
+            portfolio.execute_order(&market_order("AAPL", dec!(-15)), &MarketData::new("AAPL", dec!(130)));
+            portfolio
+        };
+
+        // FIFO closes the first lot and half the second, leaving 5 bought at 120
+        let fifo = sell_across_lots(LotMethod::Fifo);
+        let gains: Vec<_> = fifo.realized_gains().iter().map(|gain| (gain.quantity, gain.opened_index, gain.gain)).collect();
+        assert_eq!(gains, vec![(dec!(10), Some(1), dec!(300)), (dec!(5), Some(2), dec!(50))]);
+        assert_eq!(fifo.holdings["AAPL"].lots.iter().map(|lot| (lot.quantity, lot.price)).collect::<Vec<_>>(), vec![(dec!(5), dec!(120))]);
+        assert_eq!(fifo.holdings["AAPL"].avg_cost, dec!(120));
+
+        let lifo = sell_across_lots(LotMethod::Lifo);
+        let gains: Vec<_> = lifo.realized_gains().iter().map(|gain| (gain.quantity, gain.opened_index, gain.gain)).collect();
+        assert_eq!(gains, vec![(dec!(10), Some(2), dec!(100)), (dec!(5), Some(1), dec!(150))]);
+        assert_eq!(lifo.holdings["AAPL"].avg_cost, dec!(100));
+        assert_eq!(lifo.realized_pl(), dec!(250));
+    }
+
+    #[test]
+    fn sector_allocation_groups_unknown_symbols() {
+        let market_data = vec![MarketData::new("AAPL", dec!(100)), MarketData::new("MSFT", dec!(200)), MarketData::new("XYZ", dec!(50))];
+        let mut portfolio = Portfolio::new(dec!(1000));
+        for (symbol, quantity) in [("AAPL", dec!(2)), ("MSFT", dec!(2)), ("XYZ", dec!(4))] {
+            let order = Order {
+                symbol: symbol.to_string(),
+                quantity,
+                order_type: OrderType::Market,
+                time_in_force: TimeInForce::Gtc,
+            };
+            portfolio.execute_order(&order, market_data.iter().find(|data| data.symbol == symbol).unwrap());
+        }
+
+        let tech = |name: &str| SymbolMeta { name: name.to_string(), sector: "Technology".to_string() };
+        let meta = HashMap::from([("AAPL".to_string(), tech("Apple")), ("MSFT".to_string(), tech("Microsoft"))]);
+
+        let allocation = portfolio.sector_allocation(&market_data, &meta);
+        assert_eq!(allocation.len(), 2);
+        assert_eq!(allocation["Technology"], 0.6);
+        assert_eq!(allocation["Unknown"], 0.2);
+    }
+
+    #[test]
+    fn orders_only_execute_during_the_session() {
+        let mut portfolio = Portfolio {
+            session: Some(TradingSession {
+                open: NaiveTime::from_hms_opt(9, 30, 0).unwrap(),
+                close: NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+            }),
+            ..Portfolio::default()
+        };
+        let at = |hour, minute| chrono::NaiveDate::from_ymd_opt(2024, 3, 1).unwrap().and_hms_opt(hour, minute, 0).unwrap();
+        let order = |order_type, time_in_force| Order {
+            symbol: "AAPL".to_string(),
+            quantity: dec!(1),
+            order_type,
+            time_in_force,
+        };
+
+        portfolio.set_clock(at(9, 0));
+        let (_, status) = portfolio.execute_order(&order(OrderType::Market, TimeInForce::Gtc), &MarketData::new("AAPL", dec!(100)));
+        assert_eq!(status, OrderStatus::Rejected(OrderError::MarketClosed));
+
+        portfolio.set_clock(at(10, 0));
+        let (_, status) = portfolio.execute_order(&order(OrderType::Market, TimeInForce::Gtc), &MarketData::new("AAPL", dec!(100)));
+        assert_eq!(status, OrderStatus::Filled);
+
+        // A resting day order expires when the clock passes the close
+        let (id, _) = portfolio.execute_order(&order(OrderType::Limit(dec!(90)), TimeInForce::Day), &MarketData::new("AAPL", dec!(100)));
+        assert_eq!(portfolio.set_clock(at(16, 5)), vec![(id, OrderStatus::Expired)]);
+        assert!(portfolio.open_orders().is_empty());
+    }
+
+    #[test]
+    fn selling_by_dollars_is_capped_at_the_held_quantity() {
+        let portfolio = long_portfolio("AAPL", dec!(10), dec!(30));
+
+        assert_eq!(portfolio.order_for_dollars("AAPL", dec!(-1000), dec!(30), OrderType::Market).quantity, dec!(-10));
+        assert_eq!(portfolio.order_for_dollars("AAPL", dec!(-100), dec!(30), OrderType::Market).quantity, dec!(-3));
+        assert_eq!(portfolio.order_for_dollars("AAPL", dec!(100), dec!(30), OrderType::Market).quantity, dec!(3));
+    }
+
+    #[test]
+    fn nan_prices_never_reach_the_portfolio() {
+        // Money is Decimal, which can't hold NaN or infinity; the loaders are
+        // where such prices are turned away
+        let mut portfolio = long_portfolio("AAPL", dec!(10), dec!(100));
+        let options = CsvOptions {
+            invalid_prices: InvalidPricePolicy::Skip,
+            ..CsvOptions::default()
+        };
+        let feed = read_market_data("AAPL,NaN\nMSFT,inf\nAAPL,110\n".as_bytes(), &options).unwrap();
+        assert_eq!(feed.len(), 1);
+
+        portfolio.apply_snapshot(0, &feed);
+        assert_eq!(portfolio.calculate_profit_loss(&feed), dec!(100));
+        assert_eq!(portfolio.cash, dec!(9000));
+    }
+
+    fn quote_with_asks(levels: &[(Decimal, Decimal)]) -> MarketData {
+        let asks = levels.iter().map(|&(price, size)| BookLevel { price, size }).collect();
+        MarketData {
+            ask: Some(levels[0].0),
+            depth: Some(OrderBook { bids: Vec::new(), asks }),
+            ..MarketData::new("AAPL", levels[0].0)
+        }
+    }
+
+    #[test]
+    fn buy_limit_sweeps_the_ask_levels_within_its_limit() {
+        let mut portfolio = Portfolio::default();
+        let quote = quote_with_asks(&[(dec!(101), dec!(5)), (dec!(100), dec!(10)), (dec!(103), dec!(50))]);
+
+        let (_, status) = portfolio.execute_order(&limit_order("AAPL", dec!(15), dec!(102)), &quote);
+
+        // 10 at 100 then 5 at 101, averaging 100.3333...
+        assert_eq!(status, OrderStatus::Filled);
+        let trade = portfolio.transactions().last().unwrap();
+        assert_eq!(trade.quantity, dec!(15));
+        assert_eq!(trade.price, dec!(1505) / dec!(15));
+        assert_eq!(portfolio.cash, INITIAL_CASH - dec!(1505));
+    }
+
+    #[test]
+    fn buy_limit_below_the_second_level_fills_partially() {
+        let mut portfolio = Portfolio::default();
+        let quote = quote_with_asks(&[(dec!(100), dec!(10)), (dec!(101), dec!(5))]);
+
+        let (_, status) = portfolio.execute_order(&limit_order("AAPL", dec!(15), dec!(100.50)), &quote);
+
+        assert_eq!(status, OrderStatus::PartiallyFilled(dec!(10)));
+        assert_eq!(portfolio.transactions().last().unwrap().price, dec!(100));
+        // The 5 shares the first level couldn't fill rest at the limit
+        let open_orders = portfolio.open_orders();
+        assert_eq!(open_orders.len(), 1);
+        assert_eq!(open_orders[0].order.quantity, dec!(5));
+        assert_eq!(open_orders[0].reserved, dec!(502.50));
+
+        // Nothing within the limit leaves the order resting
+        let (_, status) = portfolio.execute_order(&limit_order("AAPL", dec!(5), dec!(99)), &quote);
+        assert_eq!(status, OrderStatus::Resting);
+    }
+
+    #[test]
+    fn time_in_force_decides_what_happens_to_an_unfilled_remainder() {
+        let quote = quote_with_asks(&[(dec!(100), dec!(10)), (dec!(101), dec!(5))]);
+        let order = |time_in_force| Order {
+            time_in_force,
+            ..limit_order("AAPL", dec!(15), dec!(100.50))
+        };
+
+        // Fill-or-kill won't take the 10 shares within its limit
+        let mut portfolio = Portfolio::default();
+        let (_, status) = portfolio.execute_order(&order(TimeInForce::Fok), &quote);
+        assert_eq!(
+            status,
+            OrderStatus::Rejected(OrderError::InsufficientLiquidity {
+                requested: dec!(15),
+                available: dec!(10),
+            })
+        );
+        assert!(portfolio.transactions().is_empty());
+        assert!(portfolio.open_orders().is_empty());
+
+        // Immediate-or-cancel takes them and drops the rest
+        let mut portfolio = Portfolio::default();
+        let (_, status) = portfolio.execute_order(&order(TimeInForce::Ioc), &quote);
+        assert_eq!(status, OrderStatus::PartiallyFilled(dec!(10)));
+        assert!(portfolio.open_orders().is_empty());
+        assert_eq!(portfolio.reserved_cash, Decimal::ZERO);
+
+        // Good-till-cancelled rests the rest, which fills once the ask drops
+        let mut portfolio = Portfolio::default();
+        let (id, status) = portfolio.execute_order(&order(TimeInForce::Gtc), &quote);
+        assert_eq!(status, OrderStatus::PartiallyFilled(dec!(10)));
+        assert_eq!(portfolio.open_orders()[0].id, id);
+        assert_eq!(portfolio.open_orders()[0].order.quantity, dec!(5));
+
+        let mut snapshot = MarketSnapshot::default();
+        snapshot.update(MarketData::new("AAPL", dec!(100.25)));
+        assert_eq!(portfolio.process_pending(&snapshot), vec![(id, OrderStatus::Filled)]);
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(15));
+        assert_eq!(portfolio.reserved_cash, Decimal::ZERO);
+    }
+
+    #[test]
+    fn preview_rejects_an_unaffordable_buy_without_changing_the_portfolio() {
+        let portfolio = Portfolio::new(dec!(1000));
+        let buy = limit_order("AAPL", dec!(20), dec!(60));
+        let before = portfolio.clone();
+
+        let preview = portfolio.preview_order(&buy, &MarketData::new("AAPL", dec!(50)));
+
+        assert!(matches!(preview.status, OrderStatus::Rejected(OrderError::InsufficientCash { .. })));
+        assert_eq!(preview.cash_change, Decimal::ZERO);
+        assert_eq!(portfolio, before);
+    }
+
+    #[test]
+    fn preview_matches_the_fill_it_predicts() {
+        let mut portfolio = Portfolio {
+            commission: CommissionModel::PerTrade(dec!(1)),
+            ..long_portfolio("AAPL", dec!(5), dec!(40))
+        };
+        let order = limit_order("AAPL", dec!(10), dec!(50));
+        let quote = MarketData::new("AAPL", dec!(45));
+        let before = portfolio.clone();
+
+        let preview = portfolio.preview_order(&order, &quote);
+        assert_eq!(portfolio, before);
+        assert_eq!(preview.fill_price, Some(dec!(50)));
+        assert_eq!(preview.position, dec!(15));
+
+        let (_, status) = portfolio.execute_order(&order, &quote);
+        assert_eq!(status, preview.status);
+        assert_eq!(portfolio.cash, before.cash + preview.cash_change);
+        assert_eq!(portfolio.cash, before.cash - dec!(501));
+    }
+
+    #[test]
+    fn displayed_cash_matches_the_displayed_trade_amounts() {
+        let mut portfolio = Portfolio {
+            commission: CommissionModel::Percentage(dec!(0.001)),
+            ..Portfolio::default()
+        };
+        let market = |quantity: Decimal| Order {
+            symbol: "AAPL".to_string(),
+            quantity,
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        };
+
+        // Each fill is worth a fraction of a cent more or less than shown
+        for (quantity, price) in [(dec!(1), dec!(10.005)), (dec!(1), dec!(10.005)), (dec!(3), dec!(7.3333)), (dec!(-2), dec!(12.3456))] {
+            portfolio.execute_order(&market(quantity), &MarketData::new("AAPL", price));
+        }
+
+        let shown: Decimal = portfolio
+            .transactions()
+            .iter()
+            .map(|trade| format!("{:.2}", round_money(-trade.quantity * trade.price - trade.commission)).parse::<Decimal>().unwrap())
+            .sum();
+        assert_eq!(format!("{:.2}", round_money(portfolio.cash)), format!("{:.2}", INITIAL_CASH + shown));
+        assert_eq!(portfolio.cash, round_money(portfolio.cash));
+    }
+
+    fn trailing_stop(symbol: &str, quantity: Decimal, offset: Decimal, time_in_force: TimeInForce) -> Order {
+        Order {
+            symbol: symbol.to_string(),
+            quantity,
+            order_type: OrderType::TrailingStop { offset },
+            time_in_force,
+        }
+    }
+
+    fn tick(portfolio: &mut Portfolio, price: Decimal) -> Vec<(OrderId, OrderStatus)> {
+        portfolio.apply_snapshot(0, &[MarketData::new("AAPL", price)])
+    }
+
+    #[test]
+    fn trailing_stops_on_one_symbol_keep_their_own_marks() {
+        let mut portfolio = long_portfolio("AAPL", dec!(20), dec!(100));
+
+        let (first, _) = portfolio.execute_order(&trailing_stop("AAPL", dec!(-10), dec!(5), TimeInForce::Gtc), &MarketData::new("AAPL", dec!(100)));
+        assert!(tick(&mut portfolio, dec!(110)).is_empty());
+
+        // Placed after the peak, the second order starts from 107 and doesn't
+        // move the first one's mark back down
+        let (second, status) = portfolio.execute_order(&trailing_stop("aapl", dec!(-10), dec!(5), TimeInForce::Gtc), &MarketData::new("AAPL", dec!(107)));
+        assert_eq!(status, OrderStatus::Resting);
+        assert!(tick(&mut portfolio, dec!(106)).is_empty());
+
+        assert_eq!(tick(&mut portfolio, dec!(105)), vec![(first, OrderStatus::Filled)]);
+        assert!(tick(&mut portfolio, dec!(103)).is_empty());
+        assert_eq!(tick(&mut portfolio, dec!(102)), vec![(second, OrderStatus::Filled)]);
+    }
+
+    #[test]
+    fn buy_and_sell_trailing_stops_track_opposite_extremes() {
+        let mut portfolio = long_portfolio("AAPL", dec!(10), dec!(100));
+        let quote = MarketData::new("AAPL", dec!(100));
+        let (sell, _) = portfolio.execute_order(&trailing_stop("AAPL", dec!(-10), dec!(5), TimeInForce::Gtc), &quote);
+        let (buy, _) = portfolio.execute_order(&trailing_stop("AAPL", dec!(10), dec!(5), TimeInForce::Gtc), &quote);
+
+        // The buy follows the low of 97 and the sell the high of 103
+        assert!(tick(&mut portfolio, dec!(97)).is_empty());
+        assert_eq!(tick(&mut portfolio, dec!(103)), vec![(buy, OrderStatus::Filled)]);
+        assert_eq!(tick(&mut portfolio, dec!(98)), vec![(sell, OrderStatus::Filled)]);
+    }
+
+    #[test]
+    fn a_replaced_trailing_stop_starts_a_new_mark() {
+        let mut portfolio = long_portfolio("AAPL", dec!(10), dec!(100));
+        let sell = |time_in_force| trailing_stop("AAPL", dec!(-10), dec!(5), time_in_force);
+
+        let (id, _) = portfolio.execute_order(&sell(TimeInForce::Gtc), &MarketData::new("AAPL", dec!(100)));
+        tick(&mut portfolio, dec!(120));
+        portfolio.cancel_order(id).unwrap();
+
+        // 114 is more than the offset below the cancelled order's peak
+        let (id, status) = portfolio.execute_order(&sell(TimeInForce::Day), &MarketData::new("AAPL", dec!(114)));
+        assert_eq!(status, OrderStatus::Resting);
+        tick(&mut portfolio, dec!(118));
+        assert_eq!(portfolio.end_of_day(), vec![(id, OrderStatus::Expired)]);
+
+        let (_, status) = portfolio.execute_order(&sell(TimeInForce::Gtc), &MarketData::new("AAPL", dec!(113)));
+        assert_eq!(status, OrderStatus::Resting);
+        assert!(tick(&mut portfolio, dec!(110)).is_empty());
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(10));
+    }
+
+    #[test]
+    fn a_long_take_profit_fires_only_once_the_target_is_reached() {
+        let mut portfolio = long_portfolio("AAPL", dec!(10), dec!(100));
+        let take_profit = Order {
+            order_type: OrderType::TakeProfit(dec!(120)),
+            ..market_order("AAPL", dec!(-10))
+        };
+
+        let (id, status) = portfolio.execute_order(&take_profit, &MarketData::new("AAPL", dec!(110)));
+        assert_eq!(status, OrderStatus::Resting);
+        assert!(tick(&mut portfolio, dec!(119.99)).is_empty());
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(10));
+
+        // A reached target fills at market, here above the target
+        assert_eq!(tick(&mut portfolio, dec!(121)), vec![(id, OrderStatus::Filled)]);
+        assert_eq!(portfolio.transactions().last().unwrap().price, dec!(121));
+        assert_eq!(portfolio.realized_pl(), dec!(210));
+    }
+
+    // Take-profit placed before the stop, so only the tie-break puts the stop first
+    fn bracket(portfolio: &mut Portfolio) -> (OrderId, OrderId) {
+        let quote = MarketData::new("AAPL", dec!(100));
+        let target = Order { order_type: OrderType::TakeProfit(dec!(105)), ..market_order("AAPL", dec!(-10)) };
+        let stop = Order { order_type: OrderType::Stop(dec!(95)), ..market_order("AAPL", dec!(-10)) };
+        let (target, _) = portfolio.execute_order(&target, &quote);
+        let (stop, _) = portfolio.execute_order(&stop, &quote);
+        portfolio.group_orders(&[target, stop]).unwrap();
+        (target, stop)
+    }
+
+    #[test]
+    fn a_filled_leg_cancels_the_rest_of_its_group() {
+        let mut portfolio = long_portfolio("AAPL", dec!(10), dec!(100));
+        let (target, stop) = bracket(&mut portfolio);
+
+        assert_eq!(tick(&mut portfolio, dec!(106)), vec![(target, OrderStatus::Filled), (stop, OrderStatus::Cancelled)]);
+        assert!(portfolio.open_orders().is_empty());
+        assert!(tick(&mut portfolio, dec!(90)).is_empty());
+        assert_eq!(portfolio.holdings["AAPL"].quantity, Decimal::ZERO);
+    }
+
+    #[test]
+    fn the_stop_fills_when_both_legs_qualify_at_once() {
+        let mut portfolio = long_portfolio("AAPL", dec!(10), dec!(100));
+        let (target, stop) = bracket(&mut portfolio);
+        let time = chrono::NaiveDate::from_ymd_opt(2024, 3, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        let bar = Ohlc {
+            time,
+            symbol: "AAPL".to_string(),
+            open: dec!(100),
+            high: dec!(106),
+            low: dec!(94),
+            close: dec!(100),
+            volume: dec!(1000),
+        };
+
+        let results = portfolio.process_pending_bars(&[bar]);
+
+        assert_eq!(results, vec![(stop, OrderStatus::Filled), (target, OrderStatus::Cancelled)]);
+        assert_eq!(portfolio.transactions().last().unwrap().price, dec!(95));
+        assert!(portfolio.open_orders().is_empty());
+    }
+
+    #[test]
+    fn a_second_order_within_the_interval_is_rate_limited() {
+        let mut portfolio = Portfolio {
+            min_order_interval: Some(Duration::from_secs(60)),
+            ..Portfolio::default()
+        };
+        let at = |second| chrono::NaiveDate::from_ymd_opt(2024, 3, 1).unwrap().and_hms_opt(10, 0, 0).unwrap() + chrono::TimeDelta::seconds(second);
+        let buy = |symbol: &str| Order {
+            symbol: symbol.to_string(),
+            quantity: dec!(1),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        };
+
+        portfolio.set_clock(at(0));
+        assert_eq!(portfolio.execute_order(&buy("AAPL"), &MarketData::new("AAPL", dec!(100))).1, OrderStatus::Filled);
+
+        portfolio.set_clock(at(30));
+        assert_eq!(portfolio.execute_order(&buy("aapl"), &MarketData::new("AAPL", dec!(100))).1, OrderStatus::Rejected(OrderError::RateLimited));
+        assert_eq!(portfolio.execute_order(&buy("MSFT"), &MarketData::new("MSFT", dec!(100))).1, OrderStatus::Filled);
+
+        portfolio.set_clock(at(60));
+        assert_eq!(portfolio.execute_order(&buy("AAPL"), &MarketData::new("AAPL", dec!(100))).1, OrderStatus::Filled);
+    }
+
+    #[test]
+    fn a_rejected_order_does_not_start_the_wait() {
+        let mut portfolio = Portfolio {
+            min_order_interval: Some(Duration::from_secs(60)),
+            min_order_ticks: Some(5),
+            ..Portfolio::new(dec!(1000))
+        };
+        portfolio.set_clock(chrono::NaiveDate::from_ymd_opt(2024, 3, 1).unwrap().and_hms_opt(10, 0, 0).unwrap());
+        let quote = MarketData::new("AAPL", dec!(100));
+
+        // Too big for the cash, then too many shares to sell
+        let (_, status) = portfolio.execute_order(&market_order("AAPL", dec!(20)), &quote);
+        assert!(matches!(status, OrderStatus::Rejected(OrderError::InsufficientCash { .. })), "{:?}", status);
+        let (_, status) = portfolio.execute_order(&market_order("AAPL", dec!(-1)), &quote);
+        assert!(matches!(status, OrderStatus::Rejected(OrderError::InsufficientShares { .. })), "{:?}", status);
+
+        assert_eq!(portfolio.execute_order(&market_order("AAPL", dec!(5)), &quote).1, OrderStatus::Filled);
+        assert_eq!(portfolio.execute_order(&market_order("AAPL", dec!(1)), &quote).1, OrderStatus::Rejected(OrderError::RateLimited));
+    }
+
+    #[test]
+    fn orders_must_be_whole_lots() {
+        let mut portfolio = Portfolio::new(dec!(100000));
+        portfolio.lot_size = Some(dec!(100));
+        let buy = |quantity| Order {
+            symbol: "AAPL".to_string(),
+            quantity,
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        };
+
+        let (_, status) = portfolio.execute_order(&buy(dec!(200)), &MarketData::new("AAPL", dec!(100)));
+        assert_eq!(status, OrderStatus::Filled);
+
+        let (_, status) = portfolio.execute_order(&buy(dec!(150)), &MarketData::new("AAPL", dec!(100)));
+        assert_eq!(status, OrderStatus::Rejected(OrderError::InvalidLotSize { quantity: dec!(150), lot_size: dec!(100) }));
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(200));
+    }
+
+    #[test]
+    fn dividends_are_paid_in_cash_by_default() {
+        let mut portfolio = long_portfolio("AAPL", dec!(10), dec!(50));
+        let cash = portfolio.cash;
+
+        portfolio.apply_dividend("AAPL", dec!(2), Some(dec!(40)));
+
+        assert_eq!(portfolio.cash, cash + dec!(20));
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(10));
+    }
+
+    #[test]
+    fn reinvested_dividends_buy_fractional_shares() {
+        let mut portfolio = long_portfolio("AAPL", dec!(10), dec!(50));
+        portfolio.reinvest_dividends = true;
+        let cash = portfolio.cash;
+
+        portfolio.apply_dividend("AAPL", dec!(2), Some(dec!(40)));
+
+        // 20 of dividends at 40 a share
+        assert_eq!(portfolio.cash, cash);
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(10.5));
+        assert_eq!(portfolio.transactions().last().unwrap().price, dec!(40));
+    }
+
+    #[test]
+    fn buy_past_the_position_limit_is_rejected_entirely() {
+        let mut portfolio = long_portfolio("AAPL", dec!(10), dec!(100));
+        portfolio.max_position_pct = Some(dec!(0.25));
+        let buy = |quantity| Order {
+            symbol: "AAPL".to_string(),
+            quantity,
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        };
+
+        // 10 more shares would make the position $2000 of $10000 equity
+        let (_, status) = portfolio.execute_order(&buy(dec!(10)), &MarketData::new("AAPL", dec!(100)));
+        assert_eq!(status, OrderStatus::Filled);
+
+        let (_, status) = portfolio.execute_order(&buy(dec!(6)), &MarketData::new("AAPL", dec!(100)));
+        let err = OrderError::PositionLimitExceeded { value: dec!(2600), limit: dec!(2500) };
+        assert_eq!(status, OrderStatus::Rejected(err));
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(20));
+        assert_eq!(portfolio.cash, dec!(8000));
+    }
+
+    fn order_strategy() -> impl Strategy<Value = Order> {
+        let symbol = prop::sample::select(vec!["AAPL", "MSFT", "AMZN"]);
+        let quantity = (1i64..5000, any::<bool>()).prop_map(|(hundredths, buy)| {
+            let quantity = Decimal::new(hundredths, 2);
+            if buy { quantity } else { -quantity }
+        });
+        let order_type = prop_oneof![
+            Just(OrderType::Market),
+            (5000i64..400000).prop_map(|cents| OrderType::Limit(Decimal::new(cents, 2))),
+        ];
+
+        (symbol, quantity, order_type).prop_map(|(symbol, quantity, order_type)| Order {
+            symbol: symbol.to_string(),
+            quantity,
+            order_type,
+            time_in_force: TimeInForce::Gtc,
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn orders_never_overdraw_or_misvalue_a_cash_account(orders in prop::collection::vec(order_strategy(), 1..40)) {
+            let market_data = vec![MarketData::new("AAPL", dec!(150.05)), MarketData::new("MSFT", dec!(280.30)), MarketData::new("AMZN", dec!(3300))];
+            let snapshot = MarketSnapshot::new(&market_data);
+            let mut portfolio = Portfolio::default();
+
+            for order in &orders {
+                portfolio.execute_order(order, snapshot.quote(&order.symbol).unwrap());
+
+                prop_assert!(portfolio.cash >= Decimal::ZERO);
+                prop_assert!(portfolio.available_cash() >= Decimal::ZERO);
+                prop_assert!(portfolio.holdings.values().all(|position| position.quantity >= Decimal::ZERO));
+
+                let marked: Decimal = portfolio
+                    .holdings
+                    .iter()
+                    .map(|(symbol, position)| snapshot.price(symbol).unwrap() * position.quantity)
+                    .sum();
+                prop_assert_eq!(portfolio.market_value(&market_data), portfolio.cash + marked);
+            }
+        }
+    }
+}
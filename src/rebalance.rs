@@ -0,0 +1,184 @@
+use std::collections::{BTreeMap, HashMap};
+
+use rust_decimal::{Decimal, RoundingStrategy};
+
+use crate::market_data::{normalize_symbol, MarketData, MarketSnapshot};
+use crate::order::{Order, OrderError, OrderStatus, OrderType, TimeInForce};
+use crate::portfolio::Portfolio;
+
+impl Portfolio {
+    // Trades towards the target weights, each a fraction of the current market
+    // value, e.g. 0.6 for 60%. Weights may add up to less than one, leaving the
+    // rest in cash, and held symbols without a target are sold off. Sells are
+    // executed before buys so their proceeds can pay for the buys. Quantities
+    // are rounded towards zero to four decimal places, and symbols without a
+    // quote are left alone. Returns each order executed with its outcome.
+    pub fn rebalance(&mut self, targets: &HashMap<String, Decimal>, current_market_data: &[MarketData]) -> Result<Vec<(Order, OrderStatus)>, OrderError> {
+        let total_weight: Decimal = targets.values().sum();
+        if targets.values().any(|weight| *weight < Decimal::ZERO) || total_weight > Decimal::ONE {
+            return Err(OrderError::InvalidWeights(total_weight));
+        }
+
+        let snapshot = MarketSnapshot::new(current_market_data);
+        let equity = self.market_value(current_market_data);
+
+        // Sorted so the orders come out in a repeatable order
+        let mut weights: BTreeMap<String, Decimal> = self.holdings.keys().map(|symbol| (symbol.clone(), Decimal::ZERO)).collect();
+        for (symbol, weight) in targets {
+            *weights.entry(normalize_symbol(symbol)).or_default() += *weight;
+        }
+
+        let mut orders = Vec::new();
+        for (symbol, weight) in weights {
+            let Some(price) = snapshot.price(&symbol) else {
+                continue;
+            };
+            let held = self.holdings.get(&symbol).map_or(Decimal::ZERO, |position| position.quantity);
+
+            let quantity = ((weight * equity - held * price) / price).round_dp_with_strategy(4, RoundingStrategy::ToZero);
+            if !quantity.is_zero() {
+                orders.push(Order {
+                    symbol,
+                    quantity,
+                    order_type: OrderType::Market,
+                    time_in_force: TimeInForce::Gtc,
+                });
+            }
+        }
+        orders.sort_by_key(|order| order.quantity > Decimal::ZERO);
+
+        let mut results = Vec::with_capacity(orders.len());
+        for order in orders {
+            let quote = snapshot.quote(&order.symbol).expect("rebalanced symbols are quoted");
+            let (_, status) = self.execute_order(&order, quote);
+            results.push((order, status));
+        }
+
+        Ok(results)
+    }
+
+    // Closes every position at market: longs are sold and shorts bought back.
+    // Returns each order executed with its outcome.
+    pub fn liquidate(&mut self, current_market_data: &[MarketData]) -> Vec<(Order, OrderStatus)> {
+        self.reduce_all(Decimal::ONE, current_market_data)
+    }
+
+    // Trades every position a fraction of the way to flat, e.g. 0.5 sells half
+    // of each long and covers half of each short. The fraction is clamped to
+    // 0..=1 and quantities are rounded towards zero to four decimal places, as in
+    // rebalance, except that a fraction of one closes positions exactly. Sells
+    // run before covering buys so their proceeds can pay for them, and symbols
+    // without a quote are left alone.
+    pub fn reduce_all(&mut self, fraction: Decimal, current_market_data: &[MarketData]) -> Vec<(Order, OrderStatus)> {
+        let fraction = fraction.clamp(Decimal::ZERO, Decimal::ONE);
+        let snapshot = MarketSnapshot::new(current_market_data);
+
+        let positions: BTreeMap<&String, Decimal> = self.holdings.iter().map(|(symbol, position)| (symbol, position.quantity)).collect();
+        let mut orders: Vec<Order> = positions
+            .into_iter()
+            .filter(|(symbol, _)| snapshot.quote(symbol).is_some())
+            .map(|(symbol, held)| Order {
+                symbol: symbol.clone(),
+                quantity: if fraction == Decimal::ONE {
+                    -held
+                } else {
+                    -(held * fraction).round_dp_with_strategy(4, RoundingStrategy::ToZero)
+                },
+                order_type: OrderType::Market,
+                time_in_force: TimeInForce::Gtc,
+            })
+            .filter(|order| !order.quantity.is_zero())
+            .collect();
+        orders.sort_by_key(|order| order.quantity > Decimal::ZERO);
+
+        let mut results = Vec::with_capacity(orders.len());
+        for order in orders {
+            let quote = snapshot.quote(&order.symbol).expect("reduced symbols are quoted");
+            let (_, status) = self.execute_order(&order, quote);
+            results.push((order, status));
+        }
+
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn sells_run_before_buys_so_proceeds_fund_them() {
+        let market_data = vec![MarketData::new("AAPL", dec!(100)), MarketData::new("MSFT", dec!(200))];
+        let mut portfolio = Portfolio::new(dec!(1000));
+        let buy = Order {
+            symbol: "AAPL".to_string(),
+            quantity: dec!(10),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        };
+        portfolio.execute_order(&buy, &market_data[0]);
+
+        // All cash is in AAPL, so the MSFT buy can only settle after the AAPL sale
+        let targets = HashMap::from([("aapl".to_string(), dec!(0.2)), ("MSFT".to_string(), dec!(0.7))]);
+        let results = portfolio.rebalance(&targets, &market_data).unwrap();
+
+        let quantities: Vec<(&str, Decimal, &OrderStatus)> = results.iter().map(|(order, status)| (order.symbol.as_str(), order.quantity, status)).collect();
+        assert_eq!(quantities, vec![("AAPL", dec!(-8), &OrderStatus::Filled), ("MSFT", dec!(3.5), &OrderStatus::Filled)]);
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(2));
+        assert_eq!(portfolio.holdings["MSFT"].quantity, dec!(3.5));
+        assert_eq!(portfolio.cash, dec!(100));
+    }
+
+    fn trade(portfolio: &mut Portfolio, symbol: &str, quantity: Decimal, price: Decimal) {
+        let order = Order {
+            symbol: symbol.to_string(),
+            quantity,
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        };
+        portfolio.execute_order(&order, &MarketData::new(symbol, price));
+    }
+
+    #[test]
+    fn reduce_all_sells_a_fraction_of_each_long() {
+        let mut portfolio = Portfolio::new(dec!(10000));
+        trade(&mut portfolio, "AAPL", dec!(3), dec!(100));
+        trade(&mut portfolio, "MSFT", dec!(10), dec!(200));
+
+        let results = portfolio.reduce_all(dec!(0.5), &[MarketData::new("AAPL", dec!(110)), MarketData::new("MSFT", dec!(190))]);
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(1.5));
+        assert_eq!(portfolio.holdings["MSFT"].quantity, dec!(5));
+        assert_eq!(portfolio.realized_pl(), dec!(15) - dec!(50));
+        assert_eq!(portfolio.transactions().len(), 4);
+    }
+
+    #[test]
+    fn liquidate_sells_longs_and_covers_shorts() {
+        let mut portfolio = Portfolio::new(dec!(10000));
+        portfolio.allow_shorting = true;
+        trade(&mut portfolio, "AAPL", dec!(10), dec!(100));
+        trade(&mut portfolio, "MSFT", dec!(-5), dec!(200));
+
+        let results = portfolio.liquidate(&[MarketData::new("AAPL", dec!(90)), MarketData::new("MSFT", dec!(180))]);
+
+        // The sale comes first, then the cover
+        let quantities: Vec<(&str, Decimal)> = results.iter().map(|(order, _)| (order.symbol.as_str(), order.quantity)).collect();
+        assert_eq!(quantities, vec![("AAPL", dec!(-10)), ("MSFT", dec!(5))]);
+        assert!(results.iter().all(|(_, status)| *status == OrderStatus::Filled));
+        assert!(portfolio.holdings.values().all(|position| position.quantity.is_zero()));
+        assert_eq!(portfolio.realized_pl(), dec!(-100) + dec!(100));
+        assert_eq!(portfolio.cash, dec!(10000));
+    }
+
+    #[test]
+    fn weights_over_one_are_rejected() {
+        let mut portfolio = Portfolio::default();
+        let targets = HashMap::from([("AAPL".to_string(), dec!(0.6)), ("MSFT".to_string(), dec!(0.5))]);
+
+        assert_eq!(portfolio.rebalance(&targets, &[]), Err(OrderError::InvalidWeights(dec!(1.1))));
+    }
+}
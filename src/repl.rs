@@ -0,0 +1,122 @@
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+
+use stock_trading_simulator::{normalize_symbol, Order, OrderType, TimeInForce};
+
+pub const USAGE: &str = "Commands:
+  buy SYMBOL QUANTITY [limit PRICE | stop PRICE | target PRICE] [gtc | day | ioc | fok]
+  sell SYMBOL QUANTITY [limit PRICE | stop PRICE | target PRICE] [gtc | day | ioc | fok]
+  preview buy|sell ...
+  liquidate
+  reduce FRACTION
+  status
+  statement
+  holdings
+  watch SYMBOL
+  unwatch SYMBOL
+  watchlist
+  day
+  dividends
+  reinvest on|off
+  replay FILE [SPEED]
+  quit";
+
+pub enum Command {
+    Order(Order),
+    Preview(Order), // Show what the order would do without placing it
+    Liquidate,
+    Reduce(Decimal), // Fraction of every position to close, e.g. 0.5
+    Status,
+    Statement, // Summary, cash ledger and positions in one block
+    Holdings,
+    Watch(String),
+    Unwatch(String),
+    Watchlist,
+    Day, // Advance the simulation by one day
+    Dividends,
+    Reinvest(bool), // Whether dividends buy more shares instead of paying cash
+    Replay { path: String, speed: f32 }, // Print a bar file's bars, paced by their times
+    Quit,
+}
+
+// Parses one line of input, returning a short description of the problem for
+// anything that isn't a valid command
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    match words.as_slice() {
+        [command] if command.eq_ignore_ascii_case("liquidate") => Ok(Command::Liquidate),
+        [command, fraction] if command.eq_ignore_ascii_case("reduce") => Ok(Command::Reduce(parse_decimal(fraction, "fraction")?)),
+        [command] if command.eq_ignore_ascii_case("status") => Ok(Command::Status),
+        [command] if command.eq_ignore_ascii_case("statement") => Ok(Command::Statement),
+        [command] if command.eq_ignore_ascii_case("holdings") => Ok(Command::Holdings),
+        [command] if command.eq_ignore_ascii_case("watchlist") => Ok(Command::Watchlist),
+        [command, symbol] if command.eq_ignore_ascii_case("watch") => Ok(Command::Watch(symbol.to_string())),
+        [command, symbol] if command.eq_ignore_ascii_case("unwatch") => Ok(Command::Unwatch(symbol.to_string())),
+        [command] if command.eq_ignore_ascii_case("day") => Ok(Command::Day),
+        [command] if command.eq_ignore_ascii_case("dividends") => Ok(Command::Dividends),
+        [command, setting] if command.eq_ignore_ascii_case("reinvest") => match setting.to_ascii_lowercase().as_str() {
+            "on" => Ok(Command::Reinvest(true)),
+            "off" => Ok(Command::Reinvest(false)),
+            _ => Err(format!("expected 'on' or 'off' after 'reinvest', not '{}'", setting)),
+        },
+        [command, path] if command.eq_ignore_ascii_case("replay") => Ok(Command::Replay { path: path.to_string(), speed: 0.0 }),
+        [command, path, speed] if command.eq_ignore_ascii_case("replay") => match speed.parse() {
+            Ok(speed) => Ok(Command::Replay { path: path.to_string(), speed }),
+            Err(_) => Err(format!("invalid speed '{}'", speed)),
+        },
+        [command] if command.eq_ignore_ascii_case("quit") => Ok(Command::Quit),
+        [command, rest @ ..] if command.eq_ignore_ascii_case("preview") => match parse_command(&rest.join(" "))? {
+            Command::Order(order) => Ok(Command::Preview(order)),
+            _ => Err("expected a buy or sell order after 'preview'".to_string()),
+        },
+        [side, symbol, quantity, rest @ ..] => {
+            let buy = if side.eq_ignore_ascii_case("buy") {
+                true
+            } else if side.eq_ignore_ascii_case("sell") {
+                false
+            } else {
+                return Err(format!("unknown command '{}'", side));
+            };
+
+            let quantity = parse_decimal(quantity, "quantity")?;
+            if quantity <= Decimal::ZERO {
+                return Err("quantity must be positive".to_string());
+            }
+
+            // An optional time in force comes last, defaulting to good till cancelled
+            let (rest, time_in_force) = match rest.split_last() {
+                Some((word, rest)) if word.eq_ignore_ascii_case("gtc") => (rest, TimeInForce::Gtc),
+                Some((word, rest)) if word.eq_ignore_ascii_case("day") => (rest, TimeInForce::Day),
+                Some((word, rest)) if word.eq_ignore_ascii_case("ioc") => (rest, TimeInForce::Ioc),
+                Some((word, rest)) if word.eq_ignore_ascii_case("fok") => (rest, TimeInForce::Fok),
+                _ => (rest, TimeInForce::Gtc),
+            };
+
+            let order_type = match rest {
+                [] => OrderType::Market,
+                [kind, price] if kind.eq_ignore_ascii_case("limit") => OrderType::Limit(parse_decimal(price, "limit price")?),
+                [kind, price] if kind.eq_ignore_ascii_case("stop") => OrderType::Stop(parse_decimal(price, "stop price")?),
+                [kind, price] if kind.eq_ignore_ascii_case("target") => OrderType::TakeProfit(parse_decimal(price, "target price")?),
+                _ => return Err("expected 'limit PRICE', 'stop PRICE' or 'target PRICE' after the quantity".to_string()),
+            };
+
+            Ok(Command::Order(Order {
+                symbol: normalize_symbol(symbol),
+                quantity: if buy { quantity } else { -quantity },
+                order_type,
+                time_in_force,
+            }))
+        }
+        [side, ..] if side.eq_ignore_ascii_case("buy") || side.eq_ignore_ascii_case("sell") => {
+            Err(format!("expected a symbol and quantity after '{}'", side))
+        }
+        [] => Err("empty command".to_string()),
+        [command, ..] => Err(format!("unknown command '{}'", command)),
+    }
+}
+
+fn parse_decimal(value: &str, name: &str) -> Result<Decimal, String> {
+    Decimal::from_str(value).map_err(|_| format!("invalid {} '{}'", name, value))
+}
@@ -0,0 +1,117 @@
+use std::io;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use axum::extract::State;
+use axum::routing::get;
+use axum::{Json, Router};
+use tokio::net::TcpListener;
+
+use crate::portfolio::{Portfolio, Trade};
+
+// A portfolio the simulation keeps updating while the server reads it
+pub type SharedPortfolio = Arc<Mutex<Portfolio>>;
+
+// GET /portfolio for the whole portfolio as JSON and GET /transactions for
+// just its transaction log
+pub fn router(portfolio: SharedPortfolio) -> Router {
+    Router::new()
+        .route("/portfolio", get(portfolio_state))
+        .route("/transactions", get(transactions))
+        .with_state(portfolio)
+}
+
+// Serves the router on addr, e.g. "127.0.0.1:3000", until the runtime stops
+pub async fn serve(addr: &str, portfolio: SharedPortfolio) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    axum::serve(listener, router(portfolio)).await
+}
+
+async fn portfolio_state(State(portfolio): State<SharedPortfolio>) -> Json<Portfolio> {
+    Json(read(&portfolio, Portfolio::clone))
+}
+
+async fn transactions(State(portfolio): State<SharedPortfolio>) -> Json<Vec<Trade>> {
+    Json(read(&portfolio, |portfolio| portfolio.transactions().to_vec()))
+}
+
+// Waits out the simulation's hold on the lock, which only lasts a step, and
+// copies what's needed so the lock isn't held while serializing. A simulation
+// thread that panicked mid-update still leaves a portfolio worth showing, so a
+// poisoned lock is read rather than turned into an error.
+fn read<T>(portfolio: &Mutex<Portfolio>, f: impl FnOnce(&Portfolio) -> T) -> T {
+    let guard = portfolio.lock().unwrap_or_else(PoisonError::into_inner);
+    f(&guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::sync::Barrier;
+    use std::thread;
+    use std::time::Duration;
+
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::market_data::MarketData;
+    use crate::order::{Order, OrderType, TimeInForce};
+
+    fn shared_with_trade() -> SharedPortfolio {
+        let mut portfolio = Portfolio::new(dec!(1000));
+        let order = Order {
+            symbol: "AAPL".to_string(),
+            quantity: dec!(2),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        };
+        let quote = MarketData::new("AAPL", dec!(100));
+        portfolio.execute_order(&order, &quote);
+        Arc::new(Mutex::new(portfolio))
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(future)
+    }
+
+    #[test]
+    fn endpoints_wait_for_a_briefly_held_lock() {
+        let shared = shared_with_trade();
+
+        let holder = {
+            let shared = Arc::clone(&shared);
+            let locked = Arc::new(Barrier::new(2));
+            let taken = Arc::clone(&locked);
+            let handle = thread::spawn(move || {
+                let mut portfolio = shared.lock().unwrap();
+                taken.wait();
+                thread::sleep(Duration::from_millis(50));
+                portfolio.cash = dec!(42);
+            });
+            locked.wait();
+            handle
+        };
+
+        let Json(portfolio) = block_on(portfolio_state(State(Arc::clone(&shared))));
+        holder.join().unwrap();
+
+        assert_eq!(portfolio.cash, dec!(42));
+    }
+
+    #[test]
+    fn a_poisoned_lock_still_serves_the_transactions() {
+        let shared = shared_with_trade();
+        let poisoner = Arc::clone(&shared);
+        let _ = thread::spawn(move || {
+            let _guard = poisoner.lock().unwrap();
+            panic!("simulation thread failed");
+        })
+        .join();
+        assert!(shared.is_poisoned());
+
+        let Json(trades) = block_on(transactions(State(shared)));
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity * trades[0].price, dec!(200));
+        assert!(serde_json::to_string(&trades).unwrap().contains("AAPL"));
+    }
+}
@@ -0,0 +1,111 @@
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+
+use crate::metrics::std_dev;
+
+// Per-period volatility below which returns are treated as flat
+const MIN_VOLATILITY: f32 = 1e-6;
+
+// Whole shares to buy so the position's volatility comes out near the target:
+// the budget is scaled by target_vol over the sample standard deviation of the
+// recent returns, both per period, and never beyond the full budget. Returns
+// too few or too calm to measure (below MIN_VOLATILITY) therefore spend the
+// whole budget rather than an unbounded amount. NaN and infinite returns are
+// left out, as they would otherwise carry through to the quantity. Zero
+// without a positive price, budget or finite target.
+pub fn volatility_target_quantity(cash_budget: Decimal, price: Decimal, recent_returns: &[f32], target_vol: f32) -> Decimal {
+    if price <= Decimal::ZERO || cash_budget <= Decimal::ZERO || !(target_vol.is_finite() && target_vol > 0.0) {
+        return Decimal::ZERO;
+    }
+
+    let finite: Vec<f32> = recent_returns.iter().copied().filter(|r| r.is_finite()).collect();
+    let volatility = std_dev(&finite);
+    let scale = if volatility < MIN_VOLATILITY { 1.0 } else { (target_vol / volatility).min(1.0) };
+    let Some(scale) = Decimal::from_f32(scale) else {
+        return Decimal::ZERO;
+    };
+
+    (cash_budget * scale / price).floor()
+}
+
+// Kelly criterion: the fraction of capital to stake on a bet won with
+// probability win_prob, paying win_loss_ratio times what a loss costs, is
+// p - (1 - p) / b. A negative edge gives 0 rather than a fraction to bet
+// against, as do a probability outside 0..=1, a ratio that isn't positive and
+// anything non-finite.
+pub fn kelly_fraction(win_prob: f32, win_loss_ratio: f32) -> f32 {
+    if !((0.0..=1.0).contains(&win_prob) && win_loss_ratio.is_finite() && win_loss_ratio > 0.0) {
+        return 0.0;
+    }
+
+    (win_prob - (1.0 - win_prob) / win_loss_ratio).max(0.0)
+}
+
+// Whole shares to buy with the Kelly fraction of cash scaled by multiplier,
+// e.g. 0.5 for half-Kelly, never spending more than all of it. Zero without a
+// positive price or cash, or a positive finite multiplier.
+pub fn kelly_quantity(cash: Decimal, price: Decimal, win_prob: f32, win_loss_ratio: f32, multiplier: f32) -> Decimal {
+    if price <= Decimal::ZERO || cash <= Decimal::ZERO || !(multiplier.is_finite() && multiplier > 0.0) {
+        return Decimal::ZERO;
+    }
+
+    let fraction = (kelly_fraction(win_prob, win_loss_ratio) * multiplier).min(1.0);
+    let Some(fraction) = Decimal::from_f32(fraction) else {
+        return Decimal::ZERO;
+    };
+
+    (cash * fraction / price).floor()
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn higher_volatility_buys_fewer_shares() {
+        // Sample deviations of about 0.0115 and 0.0577 against a 0.01 target
+        let calm = [0.01, -0.01, 0.01, -0.01];
+        let wild = [0.05, -0.05, 0.05, -0.05];
+
+        let calm_quantity = volatility_target_quantity(dec!(10000), dec!(100), &calm, 0.01);
+        let wild_quantity = volatility_target_quantity(dec!(10000), dec!(100), &wild, 0.01);
+
+        assert_eq!(calm_quantity, dec!(86));
+        assert_eq!(wild_quantity, dec!(17));
+    }
+
+    #[test]
+    fn non_finite_inputs_do_not_reach_the_quantity() {
+        let returns = [0.05, f32::NAN, -0.05, f32::INFINITY, 0.05, -0.05];
+
+        assert_eq!(volatility_target_quantity(dec!(10000), dec!(100), &returns, 0.01), dec!(17));
+        assert_eq!(volatility_target_quantity(dec!(10000), dec!(100), &returns, f32::NAN), Decimal::ZERO);
+    }
+
+    #[test]
+    fn kelly_sizes_a_known_edge() {
+        // A 60% chance of winning even money: stake 20%, or 10% at half-Kelly
+        assert!((kelly_fraction(0.6, 1.0) - 0.2).abs() < 1e-6);
+        assert_eq!(kelly_quantity(dec!(10000), dec!(100), 0.6, 1.0, 1.0), dec!(20));
+        assert_eq!(kelly_quantity(dec!(10000), dec!(100), 0.6, 1.0, 0.5), dec!(10));
+        // A coin flip paying 3:1 stakes 0.5 - 0.5 / 3, a third
+        assert_eq!(kelly_quantity(dec!(10000), dec!(100), 0.5, 3.0, 1.0), dec!(33));
+    }
+
+    #[test]
+    fn negative_edge_clamps_to_zero() {
+        assert_eq!(kelly_fraction(0.4, 1.0), 0.0);
+        assert_eq!(kelly_quantity(dec!(10000), dec!(100), 0.4, 1.0, 0.5), Decimal::ZERO);
+        assert_eq!(kelly_fraction(f32::NAN, 1.0), 0.0);
+        assert_eq!(kelly_fraction(0.6, 0.0), 0.0);
+    }
+
+    #[test]
+    fn flat_returns_clamp_to_the_full_budget() {
+        assert_eq!(volatility_target_quantity(dec!(10000), dec!(100), &[0.0, 0.0, 0.0], 0.01), dec!(100));
+        assert_eq!(volatility_target_quantity(dec!(10000), dec!(100), &[], 0.01), dec!(100));
+        assert_eq!(volatility_target_quantity(dec!(10000), Decimal::ZERO, &[0.05, -0.05], 0.01), Decimal::ZERO);
+    }
+}
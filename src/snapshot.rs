@@ -0,0 +1,163 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::money::round_money;
+use crate::portfolio::Portfolio;
+
+// Cash and holdings captured at one point in time, to diff against later
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PortfolioSnapshot {
+    pub cash: Decimal,
+    pub holdings: BTreeMap<String, Decimal>, // Symbol to quantity, flat positions left out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Opened, // Not held in the snapshot, held now
+    Closed, // Held in the snapshot, flat now
+    Changed, // Held in both with a different quantity
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HoldingChange {
+    pub symbol: String,
+    pub before: Decimal, // Zero when opened
+    pub after: Decimal, // Zero when closed
+    pub kind: ChangeKind,
+}
+
+impl HoldingChange {
+    pub fn delta(&self) -> Decimal {
+        self.after - self.before
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortfolioDiff {
+    pub cash_delta: Decimal, // Current cash less the snapshot's
+    pub changes: Vec<HoldingChange>, // Sorted by symbol; unchanged holdings are left out
+}
+
+impl PortfolioDiff {
+    pub fn is_empty(&self) -> bool {
+        self.cash_delta.is_zero() && self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for PortfolioDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cash: {:+.2}", round_money(self.cash_delta))?;
+        for change in &self.changes {
+            let kind = match change.kind {
+                ChangeKind::Opened => "opened",
+                ChangeKind::Closed => "closed",
+                ChangeKind::Changed => "changed",
+            };
+            write!(f, "\n{} {}: {} -> {} ({:+})", change.symbol, kind, change.before, change.after, change.delta())?;
+        }
+        Ok(())
+    }
+}
+
+impl Portfolio {
+    pub fn snapshot(&self) -> PortfolioSnapshot {
+        PortfolioSnapshot {
+            cash: self.cash,
+            holdings: self.quantities(),
+        }
+    }
+
+    // What has changed since the snapshot was taken: the cash delta and every
+    // symbol whose quantity differs, including ones opened or closed since
+    pub fn diff(&self, snapshot: &PortfolioSnapshot) -> PortfolioDiff {
+        let current = self.quantities();
+        let symbols: BTreeSet<&String> = current.keys().chain(snapshot.holdings.keys()).collect();
+
+        let changes = symbols
+            .into_iter()
+            .filter_map(|symbol| {
+                let (kind, before, after) = match (snapshot.holdings.get(symbol), current.get(symbol)) {
+                    (None, Some(&after)) => (ChangeKind::Opened, Decimal::ZERO, after),
+                    (Some(&before), None) => (ChangeKind::Closed, before, Decimal::ZERO),
+                    (Some(&before), Some(&after)) if before != after => (ChangeKind::Changed, before, after),
+                    _ => return None,
+                };
+                Some(HoldingChange {
+                    symbol: symbol.clone(),
+                    before,
+                    after,
+                    kind,
+                })
+            })
+            .collect();
+
+        PortfolioDiff {
+            cash_delta: self.cash - snapshot.cash,
+            changes,
+        }
+    }
+
+    fn quantities(&self) -> BTreeMap<String, Decimal> {
+        self.holdings
+            .iter()
+            .filter(|(_, position)| !position.quantity.is_zero())
+            .map(|(symbol, position)| (symbol.clone(), position.quantity))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::market_data::MarketData;
+    use crate::order::{Order, OrderType, TimeInForce};
+
+    fn trade(portfolio: &mut Portfolio, symbol: &str, quantity: Decimal, price: Decimal) {
+        let order = Order {
+            symbol: symbol.to_string(),
+            quantity,
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        };
+        portfolio.execute_order(&order, &MarketData::new(symbol, price));
+    }
+
+    #[test]
+    fn diff_reports_opened_closed_and_changed_holdings() {
+        let mut portfolio = Portfolio::default();
+        trade(&mut portfolio, "AAPL", dec!(10), dec!(100));
+        trade(&mut portfolio, "MSFT", dec!(5), dec!(200));
+        trade(&mut portfolio, "TSLA", dec!(1), dec!(300));
+        let snapshot = portfolio.snapshot();
+
+        trade(&mut portfolio, "AAPL", dec!(-4), dec!(100));
+        trade(&mut portfolio, "MSFT", dec!(-5), dec!(200));
+        trade(&mut portfolio, "GOOG", dec!(2), dec!(50));
+
+        let diff = portfolio.diff(&snapshot);
+
+        assert_eq!(diff.cash_delta, dec!(400) + dec!(1000) - dec!(100));
+        let changes: Vec<(&str, ChangeKind, Decimal)> = diff.changes.iter().map(|c| (c.symbol.as_str(), c.kind, c.delta())).collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("AAPL", ChangeKind::Changed, dec!(-4)),
+                ("GOOG", ChangeKind::Opened, dec!(2)),
+                ("MSFT", ChangeKind::Closed, dec!(-5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_against_an_unchanged_portfolio_is_empty() {
+        let mut portfolio = Portfolio::default();
+        trade(&mut portfolio, "AAPL", dec!(10), dec!(100));
+
+        assert!(portfolio.diff(&portfolio.snapshot()).is_empty());
+    }
+}
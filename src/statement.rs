@@ -0,0 +1,154 @@
+use rust_decimal::Decimal;
+
+use crate::market_data::MarketData;
+use crate::money::round_money;
+use crate::portfolio::{Portfolio, Trade};
+
+// One line of the cash ledger behind Portfolio::statement
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedgerEntry {
+    pub trade: Option<Trade>, // None for cash that moved outside of trades: dividends, interest and fees
+    pub amount: Decimal, // Change to cash, negative for buys
+    pub balance: Decimal, // Cash after the entry
+}
+
+impl Portfolio {
+    // Every change to cash since the start, in order, each balance being the
+    // previous one plus the amount so the last always equals cash. Trades
+    // record the cash they left, so anything between trades that moved cash
+    // shows up as its own entry before the next trade, and again at the end.
+    pub fn cash_ledger(&self) -> Vec<LedgerEntry> {
+        let mut entries = Vec::new();
+        let mut balance = self.initial_cash();
+
+        for trade in self.transactions() {
+            // The cash change process_order made. A reinvested dividend buys
+            // with cash that was never paid out, so it is credited first.
+            let amount = round_money(-trade.quantity * trade.price - trade.commission);
+            push_other(&mut entries, &mut balance, trade.cash_balance - amount);
+            balance += amount;
+            entries.push(LedgerEntry { trade: Some(trade.clone()), amount, balance });
+        }
+        push_other(&mut entries, &mut balance, self.cash);
+
+        entries
+    }
+
+    // A broker-style statement: the account summary, then the cash ledger
+    // with the running balance after every trade, then the open positions
+    // with their unrealized profit or loss at current prices
+    pub fn statement(&self, current_market_data: &[MarketData]) -> String {
+        self.statement_with(current_market_data, |_, text| text)
+    }
+
+    // statement with every profit or loss figure passed through paint along
+    // with its value, as holdings_table_with does
+    pub fn statement_with(&self, current_market_data: &[MarketData], paint: impl Fn(Decimal, String) -> String) -> String {
+        let profit_loss = |value: Decimal| paint(value, format!("{:>14.2}", round_money(value)));
+        let percent = self.percent_return(current_market_data).map_or("n/a".to_string(), |value| format!("{:.2}%", value * 100.0));
+        let mut lines = vec![
+            "Account statement".to_string(),
+            format!("  Starting cash:  {:>14.2}", round_money(self.initial_cash())),
+            format!("  Cash:           {:>14.2}", round_money(self.cash)),
+            format!("  Total equity:   {:>14.2}", round_money(self.market_value(current_market_data))),
+            format!("  Realized P/L:   {}", profit_loss(self.realized_pl())),
+            format!("  Unrealized P/L: {}", profit_loss(self.unrealized_pl(current_market_data))),
+            format!("  Total return:   {:>14}", percent),
+            String::new(),
+            "Trades".to_string(),
+            format!(
+                "  {:>5} {:<8} {:>12} {:>12} {:>10} {:>14} {:>14}",
+                "#", "Symbol", "Quantity", "Price", "Commission", "Amount", "Balance"
+            ),
+            format!("  {:<66} {:>14.2}", "Opening balance", round_money(self.initial_cash())),
+        ];
+
+        for entry in self.cash_ledger() {
+            let (amount, balance) = (round_money(entry.amount), round_money(entry.balance));
+            lines.push(match entry.trade {
+                Some(trade) => format!(
+                    "  {:>5} {:<8} {:>12} {:>12.2} {:>10.2} {:>14.2} {:>14.2}",
+                    trade.sequence,
+                    trade.symbol,
+                    trade.quantity.round_dp(4).normalize(),
+                    round_money(trade.price),
+                    round_money(trade.commission),
+                    amount,
+                    balance
+                ),
+                None => format!("  {:<51} {:>14.2} {:>14.2}", "Dividends, interest and fees", amount, balance),
+            });
+        }
+
+        lines.push(String::new());
+        lines.push("Positions".to_string());
+        if self.holdings.values().all(|position| position.quantity.is_zero()) {
+            lines.push("  No open positions".to_string());
+        } else {
+            let table = self.holdings_table_with(current_market_data, &paint);
+            lines.extend(table.lines().map(|line| format!("  {}", line)));
+        }
+
+        lines.join("\n")
+    }
+}
+
+// Records whatever took the balance to `to` outside of trades
+fn push_other(entries: &mut Vec<LedgerEntry>, balance: &mut Decimal, to: Decimal) {
+    if to != *balance {
+        entries.push(LedgerEntry { trade: None, amount: to - *balance, balance: to });
+        *balance = to;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::order::{Order, OrderType, TimeInForce};
+    use crate::portfolio::CommissionModel;
+
+    fn trade(portfolio: &mut Portfolio, symbol: &str, quantity: Decimal, price: Decimal) {
+        let order = Order {
+            symbol: symbol.to_string(),
+            quantity,
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        };
+        portfolio.execute_order(&order, &MarketData::new(symbol, price));
+    }
+
+    #[test]
+    fn running_balance_ends_at_cash() {
+        let mut portfolio = Portfolio::new(dec!(10000));
+        portfolio.commission = CommissionModel::Percentage(dec!(0.001));
+
+        trade(&mut portfolio, "AAPL", dec!(10), dec!(100.005));
+        portfolio.apply_dividend("AAPL", dec!(0.24), None);
+        trade(&mut portfolio, "MSFT", dec!(3), dec!(333.333));
+        portfolio.reinvest_dividends = true;
+        portfolio.apply_dividend("AAPL", dec!(0.5), Some(dec!(101)));
+        trade(&mut portfolio, "AAPL", dec!(-4), dec!(110.10));
+        portfolio.accrue_interest(dec!(0.05), 30);
+
+        let ledger = portfolio.cash_ledger();
+        let mut balance = portfolio.initial_cash();
+        for entry in &ledger {
+            balance += entry.amount;
+            assert_eq!(entry.balance, balance);
+            if let Some(trade) = &entry.trade {
+                assert_eq!(entry.balance, trade.cash_balance);
+            }
+        }
+        assert_eq!(ledger.last().unwrap().balance, portfolio.cash);
+        // Four trades, the cash dividend, the reinvested one and the interest
+        assert_eq!(ledger.len(), 7);
+
+        let market_data = [MarketData::new("AAPL", dec!(105)), MarketData::new("MSFT", dec!(330))];
+        let statement = portfolio.statement(&market_data);
+        let (trades, positions) = statement.split_once("\n\nPositions\n").unwrap();
+        assert!(trades.lines().last().unwrap().ends_with(&format!(" {:.2}", portfolio.cash)));
+        assert!(positions.contains("MSFT"));
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+
+use crate::market_data::{find_market_price, MarketData};
+use crate::order::{Order, OrderType, TimeInForce};
+use crate::portfolio::Portfolio;
+
+// Decides what to trade as a backtest replays market snapshots
+pub trait Strategy {
+    // Called once per snapshot, after resting orders have been re-evaluated.
+    // The returned orders are executed against the same snapshot.
+    fn on_tick(&mut self, portfolio: &mut Portfolio, snapshot: &[MarketData]) -> Vec<Order>;
+}
+
+// Spends a fixed amount of cash on one symbol at the first tick that quotes it
+// and holds from then on
+#[derive(Debug, Clone)]
+pub struct BuyAndHold {
+    pub symbol: String,
+    pub amount: Decimal, // Cash to spend, rounded down to whole shares
+    bought: bool,
+}
+
+impl BuyAndHold {
+    pub fn new(symbol: &str, amount: Decimal) -> Self {
+        BuyAndHold {
+            symbol: symbol.to_string(),
+            amount,
+            bought: false,
+        }
+    }
+}
+
+impl Strategy for BuyAndHold {
+    fn on_tick(&mut self, _portfolio: &mut Portfolio, snapshot: &[MarketData]) -> Vec<Order> {
+        if self.bought {
+            return Vec::new();
+        }
+        let Some(price) = find_market_price(snapshot, &self.symbol) else {
+            return Vec::new();
+        };
+
+        self.bought = true;
+        let quantity = (self.amount / price).floor();
+        if quantity.is_zero() {
+            return Vec::new();
+        }
+
+        vec![Order {
+            symbol: self.symbol.clone(),
+            quantity,
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        }]
+    }
+}
+
+// Buys a fixed quantity of a symbol when its short moving average crosses
+// above the long one, and sells whatever is held when it crosses back below
+#[derive(Debug, Clone)]
+pub struct MovingAverageCrossover {
+    pub short_period: usize,
+    pub long_period: usize,
+    pub quantity: Decimal, // Shares bought on each upward crossing
+    history: HashMap<String, Vec<Decimal>>, // Symbol to the last long_period prices
+    above: HashMap<String, bool>, // Symbol to whether the short average was above the long one
+}
+
+impl MovingAverageCrossover {
+    pub fn new(short_period: usize, long_period: usize, quantity: Decimal) -> Self {
+        MovingAverageCrossover {
+            short_period,
+            long_period,
+            quantity,
+            history: HashMap::new(),
+            above: HashMap::new(),
+        }
+    }
+}
+
+impl Strategy for MovingAverageCrossover {
+    fn on_tick(&mut self, portfolio: &mut Portfolio, snapshot: &[MarketData]) -> Vec<Order> {
+        let mut orders = Vec::new();
+        if self.short_period == 0 || self.long_period == 0 {
+            return orders;
+        }
+
+        for data in snapshot {
+            let prices = self.history.entry(data.symbol.clone()).or_default();
+            prices.push(data.price);
+            if prices.len() > self.long_period {
+                prices.remove(0);
+            }
+            if prices.len() < self.long_period {
+                continue;
+            }
+
+            let short = average(&prices[prices.len() - self.short_period.min(prices.len())..]);
+            let long = average(prices);
+            let above = short > long;
+
+            // The first full window only sets the starting side
+            let Some(was_above) = self.above.insert(data.symbol.clone(), above) else {
+                continue;
+            };
+
+            let held = portfolio.holdings.get(&data.symbol).map_or(Decimal::ZERO, |position| position.quantity);
+            if above && !was_above {
+                orders.push(Order {
+                    symbol: data.symbol.clone(),
+                    quantity: self.quantity,
+                    order_type: OrderType::Market,
+                    time_in_force: TimeInForce::Gtc,
+                });
+            } else if !above && was_above && held > Decimal::ZERO {
+                orders.push(Order {
+                    symbol: data.symbol.clone(),
+                    quantity: -held,
+                    order_type: OrderType::Market,
+                    time_in_force: TimeInForce::Gtc,
+                });
+            }
+        }
+
+        orders
+    }
+}
+
+fn average(prices: &[Decimal]) -> Decimal {
+    prices.iter().sum::<Decimal>() / Decimal::from(prices.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::backtest::backtest;
+
+    fn series(symbol: &str, prices: &[Decimal]) -> Vec<Vec<MarketData>> {
+        prices.iter().map(|&price| vec![MarketData::new(symbol, price)]).collect()
+    }
+
+    #[test]
+    fn buy_and_hold_buys_once_when_the_symbol_is_first_quoted() {
+        let mut snapshots = series("MSFT", &[dec!(200)]);
+        snapshots.extend(series("AAPL", &[dec!(30), dec!(35), dec!(25)]));
+
+        let portfolio = backtest(Portfolio::default(), snapshots, &mut BuyAndHold::new("AAPL", dec!(1000)));
+
+        assert_eq!(portfolio.transactions().len(), 1);
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(33));
+        assert_eq!(portfolio.cash, dec!(9010));
+    }
+
+    #[test]
+    fn crossover_buys_on_the_way_up_and_sells_on_the_way_down() {
+        // The short average crosses above at 13, below at 8 and above again at 15
+        let prices = [dec!(10), dec!(10), dec!(10), dec!(13), dec!(14), dec!(8), dec!(9), dec!(15)];
+
+        let portfolio = backtest(Portfolio::default(), series("AAPL", &prices), &mut MovingAverageCrossover::new(2, 3, dec!(10)));
+
+        let trades: Vec<_> = portfolio.transactions().iter().map(|trade| (trade.quantity, trade.price)).collect();
+        assert_eq!(trades, vec![(dec!(10), dec!(13)), (dec!(-10), dec!(8)), (dec!(10), dec!(15))]);
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(10));
+        assert_eq!(portfolio.cash, dec!(9800));
+    }
+}
@@ -0,0 +1,130 @@
+use std::io::{self, BufRead};
+
+use crate::market_data::{parse_line, MarketSnapshot};
+use crate::portfolio::Portfolio;
+use crate::strategy::Strategy;
+
+// The streaming analogue of load_market_data, for a live feed piped in with
+// something like `tail -f feed.csv`: each symbol,price line updates the running
+// snapshot as it arrives and resting orders are re-evaluated against it, with
+// the outcomes logged. Blank, partial or otherwise unparsable lines are logged
+// and skipped rather than ending the stream. Every quote is one tick of the
+// portfolio's min_order_ticks throttle. Returns the last quote seen for every
+// symbol once the input ends.
+pub fn run_stream(portfolio: &mut Portfolio, reader: impl BufRead) -> io::Result<MarketSnapshot> {
+    stream(portfolio, reader, None)
+}
+
+// run_stream with a strategy trading on the feed: after resting orders are
+// re-evaluated against each quote, the strategy sees the running snapshot and
+// the orders it returns go through execute_order, throttle included
+pub fn run_stream_with_strategy(portfolio: &mut Portfolio, reader: impl BufRead, strategy: &mut dyn Strategy) -> io::Result<MarketSnapshot> {
+    stream(portfolio, reader, Some(strategy))
+}
+
+fn stream(portfolio: &mut Portfolio, reader: impl BufRead, mut strategy: Option<&mut dyn Strategy>) -> io::Result<MarketSnapshot> {
+    let mut snapshot = MarketSnapshot::default();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let content = line.trim_start_matches('\u{feff}').trim();
+        if content.is_empty() {
+            continue;
+        }
+
+        match parse_line(content, ',') {
+            Ok(quote) => {
+                snapshot.update(quote);
+                portfolio.advance_tick();
+                portfolio.process_pending(&snapshot);
+
+                let Some(strategy) = strategy.as_deref_mut() else {
+                    continue;
+                };
+                for order in strategy.on_tick(portfolio, &snapshot.quotes()) {
+                    if let Some(quote) = snapshot.quote(&order.symbol) {
+                        let (id, status) = portfolio.execute_order(&order, quote);
+                        log::info!("order {} on line {}: {}", id, index + 1, status);
+                    }
+                }
+            }
+            Err(_) => log::warn!("skipping unreadable quote on line {}: {}", index + 1, line),
+        }
+    }
+
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use rust_decimal_macros::dec;
+
+    use super::*;
+    use crate::market_data::MarketData;
+    use crate::order::{Order, OrderError, OrderStatus, OrderType, TimeInForce};
+
+    fn buy_one(symbol: &str) -> Order {
+        Order {
+            symbol: symbol.to_string(),
+            quantity: dec!(1),
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+        }
+    }
+
+    // Buys one share of every symbol quoted, on every quote
+    struct BuyEverything;
+
+    impl Strategy for BuyEverything {
+        fn on_tick(&mut self, _portfolio: &mut Portfolio, snapshot: &[MarketData]) -> Vec<Order> {
+            snapshot.iter().map(|quote| buy_one(&quote.symbol)).collect()
+        }
+    }
+
+    #[test]
+    fn streamed_quotes_fill_resting_orders() {
+        let mut portfolio = Portfolio::default();
+        let buy = Order {
+            symbol: "AAPL".to_string(),
+            quantity: dec!(10),
+            order_type: OrderType::Limit(dec!(95)),
+            time_in_force: TimeInForce::Gtc,
+        };
+        let quote = MarketData::new("AAPL", dec!(100));
+        assert_eq!(portfolio.execute_order(&buy, &quote).1, OrderStatus::Resting);
+
+        // A blank line, a partial one and a garbled price along the way
+        let feed = "AAPL,99\n\nAAPL\nAAPL,9x\nMSFT,300\nAAPL,94";
+        let snapshot = run_stream(&mut portfolio, Cursor::new(feed)).unwrap();
+
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(10));
+        assert!(portfolio.open_orders().is_empty());
+        assert_eq!(snapshot.price("AAPL"), Some(dec!(94)));
+        assert_eq!(snapshot.price("MSFT"), Some(dec!(300)));
+    }
+
+    #[test]
+    fn the_tick_throttle_spaces_out_streamed_orders_per_symbol() {
+        let mut portfolio = Portfolio::new(dec!(100000));
+        portfolio.min_order_ticks = Some(3);
+
+        // The strategy asks for both symbols on every quote once both are
+        // quoted, but AAPL is only bought on ticks 1 and 4 and MSFT on 2 and 5
+        let feed = "AAPL,100\nMSFT,300\nAAPL,101\nAAPL,102\nMSFT,301\n";
+        run_stream_with_strategy(&mut portfolio, Cursor::new(feed), &mut BuyEverything).unwrap();
+
+        assert_eq!(portfolio.holdings["AAPL"].quantity, dec!(2));
+        assert_eq!(portfolio.holdings["MSFT"].quantity, dec!(2));
+        let prices: Vec<_> = portfolio.transactions().iter().map(|trade| trade.price).collect();
+        assert_eq!(prices, [dec!(100), dec!(300), dec!(102), dec!(301)]);
+
+        // Once the window has passed, a direct order goes through again
+        let quote = MarketData::new("AAPL", dec!(103));
+        assert_eq!(portfolio.execute_order(&buy_one("AAPL"), &quote).1, OrderStatus::Rejected(OrderError::RateLimited));
+        portfolio.advance_tick();
+        portfolio.advance_tick();
+        assert_eq!(portfolio.execute_order(&buy_one("AAPL"), &quote).1, OrderStatus::Filled);
+    }
+}
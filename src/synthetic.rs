@@ -0,0 +1,37 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+
+use crate::market_data::MarketData;
+
+// Smallest price the walk can reach, so quotes stay strictly positive
+const MIN_PRICE: f32 = 0.01;
+
+// Geometric random walk of steps quotes starting at start_price. Each step
+// multiplies the price by exp(volatility * z) for a standard normal z, so the
+// log price moves by volatility standard deviations per step. The same seed
+// always gives the same series.
+pub fn generate_random_walk(symbol: &str, start_price: f32, steps: usize, volatility: f32, seed: u64) -> Vec<MarketData> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut price = start_price.max(MIN_PRICE);
+    let mut series = Vec::with_capacity(steps);
+
+    for _ in 0..steps {
+        series.push(MarketData::new(symbol, Decimal::from_f32(price).map_or(Decimal::MAX, |price| price.round_dp(4))));
+
+        let next = price * (volatility * standard_normal(&mut rng)).exp();
+        if next.is_finite() {
+            price = next.max(MIN_PRICE);
+        }
+    }
+
+    series
+}
+
+// Box-Muller transform of two uniform samples
+fn standard_normal(rng: &mut impl Rng) -> f32 {
+    let u1: f32 = rng.gen_range(f32::EPSILON..1.0);
+    let u2: f32 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
@@ -0,0 +1,78 @@
+use rust_decimal::Decimal;
+
+use crate::market_data::{normalize_symbol, MarketData, MarketSnapshot};
+use crate::money::round_money;
+
+// Symbols followed for their quotes, whether or not they are held
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Watchlist {
+    symbols: Vec<String>, // Normalized, in the order they were added
+}
+
+impl Watchlist {
+    pub fn new() -> Self {
+        Watchlist::default()
+    }
+
+    // Returns false if the symbol was already being watched
+    pub fn add(&mut self, symbol: &str) -> bool {
+        let symbol = normalize_symbol(symbol);
+        if symbol.is_empty() || self.symbols.contains(&symbol) {
+            return false;
+        }
+
+        self.symbols.push(symbol);
+        true
+    }
+
+    // Returns false if the symbol wasn't being watched
+    pub fn remove(&mut self, symbol: &str) -> bool {
+        let symbol = normalize_symbol(symbol);
+        let before = self.symbols.len();
+        self.symbols.retain(|watched| *watched != symbol);
+        self.symbols.len() != before
+    }
+
+    pub fn symbols(&self) -> &[String] {
+        &self.symbols
+    }
+
+    // Each watched symbol with its current price, None when it isn't quoted
+    pub fn quotes(&self, current_market_data: &[MarketData]) -> Vec<(&str, Option<Decimal>)> {
+        let snapshot = MarketSnapshot::new(current_market_data);
+        self.symbols.iter().map(|symbol| (symbol.as_str(), snapshot.price(symbol))).collect()
+    }
+
+    pub fn show(&self, current_market_data: &[MarketData]) {
+        for (symbol, price) in self.quotes(current_market_data) {
+            match price {
+                Some(price) => println!("  {} ${:.2}", symbol, round_money(price)),
+                None => println!("  {} no market data", symbol),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn symbols_are_normalized_before_deduplicating() {
+        let mut watchlist = Watchlist::new();
+
+        assert!(watchlist.add("aapl"));
+        assert!(!watchlist.add(" AAPL "));
+        assert!(watchlist.add("msft"));
+        assert_eq!(watchlist.symbols(), ["AAPL", "MSFT"]);
+
+        let market_data = vec![MarketData::new("AAPL", dec!(150.05))];
+        assert_eq!(watchlist.quotes(&market_data), vec![("AAPL", Some(dec!(150.05))), ("MSFT", None)]);
+
+        assert!(watchlist.remove("Aapl"));
+        assert!(!watchlist.remove("AAPL"));
+        assert_eq!(watchlist.symbols(), ["MSFT"]);
+    }
+}